@@ -0,0 +1,234 @@
+//! Transactional, batched notifications.
+//!
+//! [`BatchSubject::batch`] runs a closure with notifications buffered rather
+//! than delivered. If the closure returns `Ok` the buffered events go out to
+//! every observer as one batch; if it returns `Err` (or panics) they are
+//! thrown away. Batches nest: an inner commit folds its events into the
+//! enclosing batch and an inner rollback discards only its own events, so
+//! nothing is delivered until the outermost batch commits.
+
+use std::cell::RefCell;
+
+use crate::event::EventSubject;
+use crate::event::IEventObserver;
+use crate::event::IEventSubject;
+
+/// Subject whose observers receive events in batches. Outside of
+/// [`batch`](Self::batch) every event is delivered as a batch of one.
+pub struct BatchSubject<'a, E, T: IEventObserver<Vec<E>>> {
+    observers: EventSubject<'a, Vec<E>, T>,
+    // One buffer per open batch, innermost last.
+    open:      RefCell<Vec<Vec<E>>>,
+}
+
+impl<'a, E, T: IEventObserver<Vec<E>> + PartialEq> BatchSubject<'a, E, T> {
+    pub fn new() -> BatchSubject<'a, E, T> {
+        BatchSubject {
+            observers: EventSubject::new(),
+            open:      RefCell::new(Vec::new()),
+        }
+    }
+
+    pub fn attach(&mut self, observer: &'a T) {
+        self.observers.attach(observer);
+    }
+
+    pub fn detach(&mut self, observer: &'a T) {
+        self.observers.detach(observer);
+    }
+
+    /// Buffers `event` in the innermost open batch, or delivers it at once
+    /// when no batch is open.
+    pub fn notify_observers(&self, event: E) {
+        if let Some(buffer) = self.open.borrow_mut().last_mut() {
+            buffer.push(event);
+            return;
+        }
+        self.observers.notify_observers(&vec![event]);
+    }
+
+    /// Runs `f` inside a batch, committing on `Ok` and rolling back on `Err`.
+    pub fn batch<R, Err>(
+        &self,
+        f: impl FnOnce(&Self) -> Result<R, Err>,
+    ) -> Result<R, Err> {
+        self.open.borrow_mut().push(Vec::new());
+        let scope = BatchScope { open: &self.open };
+        let result = f(self);
+        let events = scope.close();
+
+        if result.is_ok() {
+            let mut open = self.open.borrow_mut();
+            if let Some(parent) = open.last_mut() {
+                parent.extend(events);
+            } else {
+                drop(open);
+                if !events.is_empty() {
+                    self.observers.notify_observers(&events);
+                }
+            }
+        }
+        result
+    }
+
+    /// Number of batches currently open.
+    pub fn depth(&self) -> usize {
+        self.open.borrow().len()
+    }
+}
+
+impl<'a, E, T: IEventObserver<Vec<E>> + PartialEq> Default
+    for BatchSubject<'a, E, T>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Pops the batch it was created for, discarding its events, unless closed
+// explicitly first. Keeps the batch stack balanced if the closure panics.
+struct BatchScope<'s, E> {
+    open: &'s RefCell<Vec<Vec<E>>>,
+}
+
+impl<E> BatchScope<'_, E> {
+    fn close(self) -> Vec<E> {
+        let events = self.open.borrow_mut().pop().unwrap_or_default();
+        std::mem::forget(self);
+        events
+    }
+}
+
+impl<E> Drop for BatchScope<'_, E> {
+    fn drop(&mut self) {
+        self.open.borrow_mut().pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic;
+    use std::panic::AssertUnwindSafe;
+
+    use super::*;
+
+    #[derive(PartialEq, Default)]
+    struct BatchRecorder {
+        batches: RefCell<Vec<Vec<i32>>>,
+    }
+
+    impl IEventObserver<Vec<i32>> for BatchRecorder {
+        fn update(&self, event: &Vec<i32>) {
+            self.batches.borrow_mut().push(event.clone());
+        }
+    }
+
+    #[test]
+    fn test_notify_outside_batch() {
+        let observer = BatchRecorder::default();
+        let mut subject = BatchSubject::new();
+        subject.attach(&observer);
+
+        subject.notify_observers(1);
+        subject.notify_observers(2);
+
+        assert_eq!(*observer.batches.borrow(), vec![vec![1], vec![2]]);
+    }
+
+    #[test]
+    fn test_commit_delivers_single_batch() {
+        let observer = BatchRecorder::default();
+        let mut subject = BatchSubject::new();
+        subject.attach(&observer);
+
+        let result: Result<&str, ()> = subject.batch(|s| {
+            s.notify_observers(1);
+            s.notify_observers(2);
+            s.notify_observers(3);
+            assert!(observer.batches.borrow().is_empty());
+            Ok("done")
+        });
+
+        assert_eq!(result, Ok("done"));
+        assert_eq!(*observer.batches.borrow(), vec![vec![1, 2, 3]]);
+    }
+
+    #[test]
+    fn test_rollback_discards() {
+        let observer = BatchRecorder::default();
+        let mut subject = BatchSubject::new();
+        subject.attach(&observer);
+
+        let result: Result<(), &str> = subject.batch(|s| {
+            s.notify_observers(1);
+            Err("invalid")
+        });
+
+        assert_eq!(result, Err("invalid"));
+        assert!(observer.batches.borrow().is_empty());
+        assert_eq!(subject.depth(), 0);
+    }
+
+    #[test]
+    fn test_nested_batches() {
+        let observer = BatchRecorder::default();
+        let mut subject = BatchSubject::new();
+        subject.attach(&observer);
+
+        let result: Result<(), ()> = subject.batch(|s| {
+            s.notify_observers(1);
+            let inner: Result<(), ()> = s.batch(|s| {
+                s.notify_observers(2);
+                Ok(())
+            });
+            assert!(inner.is_ok());
+            let rolled_back: Result<(), ()> = s.batch(|s| {
+                s.notify_observers(99);
+                Err(())
+            });
+            assert!(rolled_back.is_err());
+            assert_eq!(s.depth(), 1);
+            s.notify_observers(3);
+            Ok(())
+        });
+
+        assert!(result.is_ok());
+        assert_eq!(*observer.batches.borrow(), vec![vec![1, 2, 3]]);
+    }
+
+    #[test]
+    fn test_outer_rollback_discards_inner_commit() {
+        let observer = BatchRecorder::default();
+        let mut subject = BatchSubject::new();
+        subject.attach(&observer);
+
+        let _: Result<(), ()> = subject.batch(|s| {
+            s.batch::<_, ()>(|s| {
+                s.notify_observers(1);
+                Ok(())
+            })?;
+            Err(())
+        });
+
+        assert!(observer.batches.borrow().is_empty());
+    }
+
+    #[test]
+    fn test_panic_rolls_back() {
+        let observer = BatchRecorder::default();
+        let mut subject = BatchSubject::new();
+        subject.attach(&observer);
+
+        let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+            let _: Result<(), ()> = subject.batch(|s| {
+                s.notify_observers(1);
+                panic!("boom");
+            });
+        }));
+
+        assert!(outcome.is_err());
+        assert_eq!(subject.depth(), 0);
+        subject.notify_observers(2);
+        assert_eq!(*observer.batches.borrow(), vec![vec![2]]);
+    }
+}
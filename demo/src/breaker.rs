@@ -0,0 +1,392 @@
+//! Circuit breakers that stop notifying observers which keep failing.
+//!
+//! A [`BreakerSubject`] keeps a breaker for each attached
+//! [`IFallibleObserver`]:
+//!
+//! - **Closed**: the observer gets every event. Failures in a row, including
+//!   calls slower than [`BreakerPolicy::slow_call`], are counted, and reaching
+//!   [`BreakerPolicy::failure_threshold`] opens the breaker.
+//! - **Open**: the observer is skipped until [`BreakerPolicy::open_for`] has
+//!   passed.
+//! - **Half-open**: the observer gets events again on probation.
+//!   [`BreakerPolicy::probes`] successes close the breaker; a single failure
+//!   opens it again.
+//!
+//! Events skipped while a breaker is open are not kept; pair this with a
+//! [`Spool`](crate::spool::Spool) if the observer must see them later.
+
+use std::cell::RefCell;
+use std::time::Duration;
+
+use crate::clock::Clock;
+use crate::clock::SystemClock;
+use crate::event::IFallibleObserver;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BreakerPolicy {
+    /// Failures in a row that open the breaker.
+    pub failure_threshold: u32,
+    /// How long an open breaker skips its observer.
+    pub open_for: Duration,
+    /// Successes needed in the half-open state to close the breaker.
+    pub probes: u32,
+    /// Calls taking longer than this count as failures even if they succeed.
+    pub slow_call: Option<Duration>,
+}
+
+impl Default for BreakerPolicy {
+    fn default() -> Self {
+        BreakerPolicy {
+            failure_threshold: 5,
+            open_for: Duration::from_secs(30),
+            probes: 1,
+            slow_call: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+/// What a [`BreakerSubject`] knows about one observer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Health {
+    pub state: CircuitState,
+    pub consecutive_failures: u32,
+    pub successes: u64,
+    pub failures: u64,
+    /// Events the observer missed while its breaker was open.
+    pub skipped: u64,
+    /// Times the breaker has opened.
+    pub trips: u64,
+    pub last_error: Option<String>,
+}
+
+struct Breaker {
+    health:      Health,
+    /// When an open breaker lets the next call through.
+    retry_at:    Duration,
+    probes_left: u32,
+}
+
+impl Breaker {
+    fn new() -> Breaker {
+        Breaker {
+            health:      Health {
+                state: CircuitState::Closed,
+                consecutive_failures: 0,
+                successes: 0,
+                failures: 0,
+                skipped: 0,
+                trips: 0,
+                last_error: None,
+            },
+            retry_at:    Duration::ZERO,
+            probes_left: 0,
+        }
+    }
+
+    fn allow(&mut self, policy: &BreakerPolicy, now: Duration) -> bool {
+        if self.health.state == CircuitState::Open {
+            if now < self.retry_at {
+                self.health.skipped += 1;
+                return false;
+            }
+            self.health.state = CircuitState::HalfOpen;
+            self.probes_left = policy.probes.max(1);
+        }
+        true
+    }
+
+    fn succeeded(&mut self) {
+        self.health.successes += 1;
+        self.health.consecutive_failures = 0;
+        if self.health.state == CircuitState::HalfOpen {
+            self.probes_left -= 1;
+            if self.probes_left == 0 {
+                self.health.state = CircuitState::Closed;
+            }
+        }
+    }
+
+    fn failed(&mut self, policy: &BreakerPolicy, now: Duration, error: String) {
+        self.health.failures += 1;
+        self.health.consecutive_failures += 1;
+        self.health.last_error = Some(error);
+        let trip = match self.health.state {
+            CircuitState::HalfOpen => true,
+            _ => self.health.consecutive_failures >= policy.failure_threshold,
+        };
+        if trip {
+            self.health.state = CircuitState::Open;
+            self.health.trips += 1;
+            self.retry_at = now + policy.open_for;
+        }
+    }
+}
+
+pub struct BreakerSubject<'a, T, C = SystemClock> {
+    observers: Vec<(&'a T, RefCell<Breaker>)>,
+    policy:    BreakerPolicy,
+    clock:     C,
+}
+
+impl<'a, T: PartialEq> BreakerSubject<'a, T> {
+    pub fn new(policy: BreakerPolicy) -> BreakerSubject<'a, T> {
+        BreakerSubject::with_clock(policy, SystemClock::new())
+    }
+}
+
+impl<'a, T: PartialEq, C: Clock> BreakerSubject<'a, T, C> {
+    pub fn with_clock(
+        policy: BreakerPolicy,
+        clock: C,
+    ) -> BreakerSubject<'a, T, C> {
+        BreakerSubject {
+            observers: Vec::new(),
+            policy,
+            clock,
+        }
+    }
+
+    /// Attaches `observer` with a closed breaker.
+    pub fn attach(&mut self, observer: &'a T) {
+        self.observers
+            .push((observer, RefCell::new(Breaker::new())));
+    }
+
+    pub fn detach(&mut self, observer: &'a T) {
+        if let Some(idx) =
+            self.observers.iter().position(|(x, _)| *x == observer)
+        {
+            self.observers.remove(idx);
+        }
+    }
+
+    /// Delivers `event` to every observer whose breaker lets it through.
+    pub fn notify_observers<E>(&self, event: &E)
+    where
+        T: IFallibleObserver<E>,
+    {
+        for (item, breaker) in self.observers.iter() {
+            let start = self.clock.now();
+            if !breaker.borrow_mut().allow(&self.policy, start) {
+                continue;
+            }
+            let result = item.try_update(event);
+            let now = self.clock.now();
+            let elapsed = now.saturating_sub(start);
+            let mut breaker = breaker.borrow_mut();
+            match result {
+                Err(error) => {
+                    breaker.failed(&self.policy, now, error.to_string());
+                },
+                Ok(())
+                    if self.policy.slow_call.is_some_and(|l| elapsed > l) =>
+                {
+                    let error = format!("call took {elapsed:?}");
+                    breaker.failed(&self.policy, now, error);
+                },
+                Ok(()) => breaker.succeeded(),
+            }
+        }
+    }
+
+    /// Health of `observer`, or `None` if it is not attached.
+    pub fn health_of(&self, observer: &T) -> Option<Health> {
+        self.observers
+            .iter()
+            .find(|(x, _)| *x == observer)
+            .map(|(_, breaker)| breaker.borrow().health.clone())
+    }
+
+    /// Health of every attached observer, in attachment order.
+    pub fn health(&self) -> Vec<(&'a T, Health)> {
+        self.observers
+            .iter()
+            .map(|(x, breaker)| (*x, breaker.borrow().health.clone()))
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.observers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.observers.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::event::ObserverError;
+
+    // Fails while `down` is set and takes `latency` of virtual time per call.
+    struct Remote<'c> {
+        clock:   &'c ManualClock,
+        down:    Cell<bool>,
+        latency: Cell<Duration>,
+        calls:   Cell<u32>,
+    }
+
+    impl<'c> Remote<'c> {
+        fn new(clock: &'c ManualClock) -> Remote<'c> {
+            Remote {
+                clock,
+                down: Cell::new(false),
+                latency: Cell::new(Duration::ZERO),
+                calls: Cell::new(0),
+            }
+        }
+    }
+
+    impl PartialEq for Remote<'_> {
+        fn eq(&self, other: &Self) -> bool {
+            std::ptr::eq(self, other)
+        }
+    }
+
+    impl IFallibleObserver<u32> for Remote<'_> {
+        fn try_update(&self, _event: &u32) -> Result<(), ObserverError> {
+            self.calls.set(self.calls.get() + 1);
+            self.clock.advance(self.latency.get());
+            if self.down.get() {
+                Err("unavailable".into())
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    fn policy() -> BreakerPolicy {
+        BreakerPolicy {
+            failure_threshold: 3,
+            open_for: Duration::from_secs(10),
+            probes: 2,
+            slow_call: Some(Duration::from_millis(500)),
+        }
+    }
+
+    #[test]
+    fn test_opens_after_consecutive_failures() {
+        let clock = ManualClock::new();
+        let remote = Remote::new(&clock);
+        let healthy = Remote::new(&clock);
+        let mut subject = BreakerSubject::with_clock(policy(), &clock);
+        subject.attach(&remote);
+        subject.attach(&healthy);
+        remote.down.set(true);
+
+        for n in 0..10 {
+            subject.notify_observers(&n);
+        }
+
+        assert_eq!(remote.calls.get(), 3);
+        assert_eq!(healthy.calls.get(), 10);
+        let health = subject.health_of(&remote).unwrap();
+        assert_eq!(health.state, CircuitState::Open);
+        assert_eq!(health.skipped, 7);
+        assert_eq!(health.trips, 1);
+        assert_eq!(health.last_error.as_deref(), Some("unavailable"));
+        assert_eq!(
+            subject.health_of(&healthy).unwrap().state,
+            CircuitState::Closed
+        );
+    }
+
+    #[test]
+    fn test_successes_reset_the_count() {
+        let clock = ManualClock::new();
+        let remote = Remote::new(&clock);
+        let mut subject = BreakerSubject::with_clock(policy(), &clock);
+        subject.attach(&remote);
+
+        for n in 0..9 {
+            // Two failures, then a success, over and over.
+            remote.down.set(n % 3 != 2);
+            subject.notify_observers(&n);
+        }
+
+        let health = subject.health_of(&remote).unwrap();
+        assert_eq!(health.state, CircuitState::Closed);
+        assert_eq!(health.failures, 6);
+        assert_eq!(remote.calls.get(), 9);
+    }
+
+    #[test]
+    fn test_half_open_probes_then_closes() {
+        let clock = ManualClock::new();
+        let remote = Remote::new(&clock);
+        let mut subject = BreakerSubject::with_clock(policy(), &clock);
+        subject.attach(&remote);
+        remote.down.set(true);
+        for n in 0..3 {
+            subject.notify_observers(&n);
+        }
+
+        clock.advance(Duration::from_secs(10));
+        remote.down.set(false);
+        subject.notify_observers(&3);
+        assert_eq!(
+            subject.health_of(&remote).unwrap().state,
+            CircuitState::HalfOpen
+        );
+        subject.notify_observers(&4);
+
+        assert_eq!(
+            subject.health_of(&remote).unwrap().state,
+            CircuitState::Closed
+        );
+        assert_eq!(remote.calls.get(), 5);
+    }
+
+    #[test]
+    fn test_failed_probe_reopens() {
+        let clock = ManualClock::new();
+        let remote = Remote::new(&clock);
+        let mut subject = BreakerSubject::with_clock(policy(), &clock);
+        subject.attach(&remote);
+        remote.down.set(true);
+        for n in 0..3 {
+            subject.notify_observers(&n);
+        }
+
+        clock.advance(Duration::from_secs(10));
+        subject.notify_observers(&3);
+        subject.notify_observers(&4);
+
+        let health = subject.health_of(&remote).unwrap();
+        assert_eq!(health.state, CircuitState::Open);
+        assert_eq!(health.trips, 2);
+        assert_eq!(health.skipped, 1);
+        assert_eq!(remote.calls.get(), 4);
+    }
+
+    #[test]
+    fn test_slow_calls_count_as_failures() {
+        let clock = ManualClock::new();
+        let remote = Remote::new(&clock);
+        let mut subject = BreakerSubject::with_clock(policy(), &clock);
+        subject.attach(&remote);
+        remote.latency.set(Duration::from_secs(1));
+
+        for n in 0..5 {
+            subject.notify_observers(&n);
+        }
+
+        let health = subject.health();
+        assert_eq!(health.len(), 1);
+        assert!(std::ptr::eq(health[0].0, &remote));
+        assert_eq!(health[0].1.state, CircuitState::Open);
+        assert_eq!(health[0].1.last_error.as_deref(), Some("call took 1s"));
+        assert_eq!(remote.calls.get(), 3);
+    }
+}
@@ -0,0 +1,435 @@
+//! Multi-producer, multi-consumer broadcast channel.
+//!
+//! Where [`Subject`](crate::observer::Subject) pushes every notification
+//! through `update`, a broadcast channel keeps events in a shared ring buffer
+//! and lets each [`Receiver`] pull them at its own pace. A receiver that falls
+//! more than `capacity` events behind is told how many it missed through
+//! [`RecvError::Lagged`] instead of silently skipping ahead.
+
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+
+/// Creates a broadcast channel retaining the last `capacity` events.
+///
+/// # Panics
+///
+/// Panics if `capacity` is zero.
+pub fn channel<E: Clone>(capacity: usize) -> (Sender<E>, Receiver<E>) {
+    assert!(capacity > 0, "broadcast capacity must be non-zero");
+    let shared = Arc::new(Shared {
+        state:     Mutex::new(State {
+            slots:     (0..capacity).map(|_| None).collect(),
+            tail:      0,
+            senders:   1,
+            receivers: 1,
+        }),
+        available: Condvar::new(),
+    });
+    let sender = Sender {
+        shared: Arc::clone(&shared),
+    };
+    (sender, Receiver { shared, next: 0 })
+}
+
+struct Shared<E> {
+    state:     Mutex<State<E>>,
+    available: Condvar,
+}
+
+impl<E> Shared<E> {
+    fn lock(&self) -> MutexGuard<'_, State<E>> {
+        self.state.lock().expect("broadcast state poisoned")
+    }
+}
+
+struct State<E> {
+    slots:     Vec<Option<E>>,
+    // Sequence number the next sent event will get.
+    tail:      u64,
+    senders:   usize,
+    receivers: usize,
+}
+
+impl<E: Clone> State<E> {
+    fn capacity(&self) -> u64 {
+        self.slots.len() as u64
+    }
+
+    // Oldest sequence number still held in the ring.
+    fn head(&self) -> u64 {
+        self.tail.saturating_sub(self.capacity())
+    }
+
+    fn slot(&self, seq: u64) -> usize {
+        (seq % self.capacity()) as usize
+    }
+
+    fn read(&self, next: &mut u64) -> Result<E, TryRecvError> {
+        let head = self.head();
+        if *next < head {
+            let missed = head - *next;
+            *next = head;
+            return Err(TryRecvError::Lagged(missed));
+        }
+        if *next == self.tail {
+            return if self.senders == 0 {
+                Err(TryRecvError::Closed)
+            } else {
+                Err(TryRecvError::Empty)
+            };
+        }
+        let event = self.slots[self.slot(*next)]
+            .clone()
+            .expect("slot inside the retained window is populated");
+        *next += 1;
+        Ok(event)
+    }
+}
+
+/// Sending half of a broadcast channel. Cloning yields another producer.
+pub struct Sender<E> {
+    shared: Arc<Shared<E>>,
+}
+
+impl<E: Clone> Sender<E> {
+    /// Publishes `event` to every receiver, returning how many there are.
+    ///
+    /// When the ring is full the oldest event is overwritten; receivers that
+    /// had not read it yet observe [`RecvError::Lagged`] on their next call.
+    pub fn send(&self, event: E) -> Result<usize, SendError<E>> {
+        let mut state = self.shared.lock();
+        if state.receivers == 0 {
+            return Err(SendError(event));
+        }
+        let idx = state.slot(state.tail);
+        state.slots[idx] = Some(event);
+        state.tail += 1;
+        let receivers = state.receivers;
+        drop(state);
+        self.shared.available.notify_all();
+        Ok(receivers)
+    }
+
+    /// Creates a receiver that sees only events sent after this call.
+    pub fn subscribe(&self) -> Receiver<E> {
+        let mut state = self.shared.lock();
+        state.receivers += 1;
+        Receiver {
+            shared: Arc::clone(&self.shared),
+            next:   state.tail,
+        }
+    }
+
+    /// Number of receivers alive now; sends fail while this is zero.
+    pub fn receiver_count(&self) -> usize {
+        self.shared.lock().receivers
+    }
+}
+
+impl<E> Clone for Sender<E> {
+    fn clone(&self) -> Self {
+        self.shared.lock().senders += 1;
+        Sender {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<E> Drop for Sender<E> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.senders -= 1;
+        if state.senders == 0 {
+            drop(state);
+            // Wake blocked receivers so they can observe the closed channel.
+            self.shared.available.notify_all();
+        }
+    }
+}
+
+/// Receiving half of a broadcast channel.
+///
+/// Cloning a receiver yields an independent cursor starting at the same
+/// position as the original.
+pub struct Receiver<E> {
+    shared: Arc<Shared<E>>,
+    next:   u64,
+}
+
+impl<E: Clone> Receiver<E> {
+    /// Returns the next event without blocking.
+    pub fn try_recv(&mut self) -> Result<E, TryRecvError> {
+        self.shared.lock().read(&mut self.next)
+    }
+
+    /// Blocks until the next event is available or every sender is gone.
+    pub fn recv(&mut self) -> Result<E, RecvError> {
+        let mut state = self.shared.lock();
+        loop {
+            match state.read(&mut self.next) {
+                Ok(event) => return Ok(event),
+                Err(TryRecvError::Empty) => {
+                    state = self
+                        .shared
+                        .available
+                        .wait(state)
+                        .expect("broadcast state poisoned");
+                },
+                Err(TryRecvError::Lagged(missed)) => {
+                    return Err(RecvError::Lagged(missed));
+                },
+                Err(TryRecvError::Closed) => return Err(RecvError::Closed),
+            }
+        }
+    }
+
+    /// Number of events sent but not yet read, including ones already lost.
+    pub fn pending(&self) -> u64 {
+        self.shared.lock().tail - self.next
+    }
+}
+
+impl<E> Clone for Receiver<E> {
+    fn clone(&self) -> Self {
+        self.shared.lock().receivers += 1;
+        Receiver {
+            shared: Arc::clone(&self.shared),
+            next:   self.next,
+        }
+    }
+}
+
+impl<E> Drop for Receiver<E> {
+    fn drop(&mut self) {
+        self.shared.lock().receivers -= 1;
+    }
+}
+
+/// Returned by [`Sender::send`] when no receiver is left; carries the event
+/// back to the caller.
+#[derive(Debug, PartialEq)]
+pub struct SendError<E>(pub E);
+
+impl<E> fmt::Display for SendError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "broadcast channel has no receivers")
+    }
+}
+
+impl<E: fmt::Debug> Error for SendError<E> {}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RecvError {
+    /// The receiver fell behind and this many events were overwritten
+    /// before it could read them. The next call resumes at the oldest
+    /// retained event.
+    Lagged(u64),
+    /// Every sender has been dropped and all retained events were read.
+    Closed,
+}
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecvError::Lagged(missed) => {
+                write!(f, "receiver lagged behind by {missed} events")
+            },
+            RecvError::Closed => write!(f, "broadcast channel closed"),
+        }
+    }
+}
+
+impl Error for RecvError {}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TryRecvError {
+    /// No event is available yet.
+    Empty,
+    /// See [`RecvError::Lagged`].
+    Lagged(u64),
+    /// See [`RecvError::Closed`].
+    Closed,
+}
+
+impl fmt::Display for TryRecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryRecvError::Empty => write!(f, "broadcast channel empty"),
+            TryRecvError::Lagged(missed) => {
+                write!(f, "receiver lagged behind by {missed} events")
+            },
+            TryRecvError::Closed => write!(f, "broadcast channel closed"),
+        }
+    }
+}
+
+impl Error for TryRecvError {}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn test_send_and_recv_in_order() {
+        let (tx, mut rx) = channel(4);
+
+        tx.send(1).unwrap();
+        tx.send(2).unwrap();
+
+        assert_eq!(rx.try_recv(), Ok(1));
+        assert_eq!(rx.try_recv(), Ok(2));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+    }
+
+    #[test]
+    fn test_wraparound() {
+        let (tx, mut rx) = channel(3);
+
+        // Interleave sends and reads so the ring wraps several times.
+        for i in 0..10 {
+            tx.send(i).unwrap();
+            tx.send(i + 100).unwrap();
+            assert_eq!(rx.try_recv(), Ok(i));
+            assert_eq!(rx.try_recv(), Ok(i + 100));
+        }
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+    }
+
+    #[test]
+    fn test_lagged_receiver() {
+        let (tx, mut rx) = channel(4);
+
+        for i in 0..10 {
+            tx.send(i).unwrap();
+        }
+
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Lagged(6)));
+        for i in 6..10 {
+            assert_eq!(rx.try_recv(), Ok(i));
+        }
+        assert_eq!(rx.pending(), 0);
+    }
+
+    #[test]
+    fn test_subscribe_skips_earlier_events() {
+        let (tx, mut rx) = channel(4);
+        tx.send("before").unwrap();
+        let mut late = tx.subscribe();
+        tx.send("after").unwrap();
+
+        assert_eq!(late.try_recv(), Ok("after"));
+        assert_eq!(rx.try_recv(), Ok("before"));
+        assert_eq!(rx.try_recv(), Ok("after"));
+    }
+
+    #[test]
+    fn test_send_without_receivers_fails() {
+        let (tx, rx) = channel(2);
+        drop(rx);
+
+        assert_eq!(tx.send(7), Err(SendError(7)));
+    }
+
+    #[test]
+    fn test_closed_after_senders_dropped() {
+        let (tx, mut rx) = channel(2);
+        let tx2 = tx.clone();
+        tx.send(1).unwrap();
+        drop(tx);
+        drop(tx2);
+
+        assert_eq!(rx.recv(), Ok(1));
+        assert_eq!(rx.recv(), Err(RecvError::Closed));
+    }
+
+    #[test]
+    fn test_many_concurrent_readers() {
+        const EVENTS: u64 = 500;
+        let (tx, rx) = channel(EVENTS as usize);
+
+        let readers: Vec<_> = (0..8)
+            .map(|_| {
+                let mut rx = rx.clone();
+                thread::spawn(move || {
+                    let mut seen = Vec::new();
+                    while let Ok(event) = rx.recv() {
+                        seen.push(event);
+                    }
+                    seen
+                })
+            })
+            .collect();
+        drop(rx);
+
+        let producers: Vec<_> = (0..2)
+            .map(|p| {
+                let tx = tx.clone();
+                thread::spawn(move || {
+                    for i in 0..EVENTS / 2 {
+                        tx.send(p * EVENTS + i).unwrap();
+                    }
+                })
+            })
+            .collect();
+        drop(tx);
+        for producer in producers {
+            producer.join().unwrap();
+        }
+
+        let expected = readers.into_iter().map(|r| r.join().unwrap());
+        let mut first = None;
+        for seen in expected {
+            assert_eq!(seen.len() as u64, EVENTS);
+            // Every reader observes the same global order.
+            match &first {
+                None => first = Some(seen),
+                Some(order) => assert_eq!(order, &seen),
+            }
+        }
+    }
+
+    #[test]
+    fn test_concurrent_readers_account_for_lag() {
+        const EVENTS: u64 = 2_000;
+        let (tx, rx) = channel(8);
+
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let mut rx = rx.clone();
+                thread::spawn(move || {
+                    let (mut received, mut missed) = (0, 0);
+                    let mut last = None;
+                    loop {
+                        match rx.recv() {
+                            Ok(event) => {
+                                assert!(last < Some(event), "out of order");
+                                last = Some(event);
+                                received += 1;
+                            },
+                            Err(RecvError::Lagged(n)) => missed += n,
+                            Err(RecvError::Closed) => break,
+                        }
+                    }
+                    (received, missed)
+                })
+            })
+            .collect();
+        drop(rx);
+
+        for i in 0..EVENTS {
+            tx.send(i).unwrap();
+        }
+        drop(tx);
+
+        for reader in readers {
+            let (received, missed) = reader.join().unwrap();
+            assert_eq!(received + missed, EVENTS);
+        }
+    }
+}
@@ -0,0 +1,324 @@
+//! A subject split into capabilities.
+//!
+//! Instead of one `&mut` subject that can do everything, the subject is
+//! reached only through handles that can each do one thing:
+//!
+//! - [`Publisher`]: notify observers.
+//! - [`Subscriber`]: attach and detach observers.
+//! - [`Admin`]: inspect and purge observers, and mint new handles.
+//!
+//! [`Admin::new`] creates an empty subject and the first handle to it.
+//! Handles share the subject and can be passed around freely. Cloning a
+//! handle shares its grant, so [`revoke`](Publisher::revoke) on any clone
+//! cuts off all of them, while handles minted separately are revoked
+//! separately. Revoking an admin also cuts off every handle it minted, even
+//! ones minted before, and so on down the chain. To give code access that
+//! can be taken back later, mint a handle for it and keep a clone. Every
+//! operation on a revoked handle fails with [`Revoked`].
+
+use std::cell::Cell;
+use std::cell::RefCell;
+use std::error::Error;
+use std::fmt;
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+use crate::event::IEventObserver;
+
+struct Core<'a, E, T> {
+    observers: RefCell<Vec<&'a T>>,
+    _event:    PhantomData<fn(&E)>,
+}
+
+/// Shared by a handle and its clones.
+struct Grant {
+    revoked: Cell<bool>,
+    /// Grant of the admin that minted the handle, if any.
+    parent:  Option<Rc<Grant>>,
+}
+
+impl Grant {
+    fn new(parent: Option<&Rc<Grant>>) -> Rc<Grant> {
+        Rc::new(Grant {
+            revoked: Cell::new(false),
+            parent:  parent.cloned(),
+        })
+    }
+
+    /// Fails if this grant or any grant it was minted under is revoked.
+    fn check(&self) -> Result<(), Revoked> {
+        let mut grant = Some(self);
+        while let Some(current) = grant {
+            if current.revoked.get() {
+                return Err(Revoked);
+            }
+            grant = current.parent.as_deref();
+        }
+        Ok(())
+    }
+}
+
+macro_rules! handle {
+    ($(#[$meta:meta])* $name:ident) => {
+        $(#[$meta])*
+        pub struct $name<'a, E, T> {
+            core:  Rc<Core<'a, E, T>>,
+            grant: Rc<Grant>,
+        }
+
+        impl<'a, E, T> $name<'a, E, T> {
+            /// Revokes this handle and every clone of it.
+            pub fn revoke(&self) {
+                self.grant.revoked.set(true);
+            }
+
+            /// Whether this handle, or the admin that minted it, is revoked.
+            pub fn is_revoked(&self) -> bool {
+                self.grant.check().is_err()
+            }
+        }
+
+        impl<E, T> Clone for $name<'_, E, T> {
+            fn clone(&self) -> Self {
+                $name {
+                    core:  Rc::clone(&self.core),
+                    grant: Rc::clone(&self.grant),
+                }
+            }
+        }
+    };
+}
+
+handle!(
+    /// Can notify observers.
+    Publisher
+);
+handle!(
+    /// Can attach and detach observers.
+    Subscriber
+);
+handle!(
+    /// Can inspect and purge observers and mint handles.
+    Admin
+);
+
+impl<'a, E, T: IEventObserver<E>> Publisher<'a, E, T> {
+    pub fn notify_observers(&self, event: &E) -> Result<(), Revoked> {
+        self.grant.check()?;
+        // Observers may hold a subscriber handle and use it while notified.
+        let observers = self.core.observers.borrow().clone();
+        for item in observers {
+            item.update(event);
+        }
+        Ok(())
+    }
+}
+
+impl<'a, E, T: PartialEq> Subscriber<'a, E, T> {
+    pub fn attach(&self, observer: &'a T) -> Result<(), Revoked> {
+        self.grant.check()?;
+        self.core.observers.borrow_mut().push(observer);
+        Ok(())
+    }
+
+    pub fn detach(&self, observer: &'a T) -> Result<(), Revoked> {
+        self.grant.check()?;
+        let mut observers = self.core.observers.borrow_mut();
+        if let Some(idx) = observers.iter().position(|x| *x == observer) {
+            observers.remove(idx);
+        }
+        Ok(())
+    }
+}
+
+impl<'a, E, T> Admin<'a, E, T> {
+    /// Creates an empty subject and returns its first admin handle.
+    pub fn new() -> Admin<'a, E, T> {
+        Admin {
+            core:  Rc::new(Core {
+                observers: RefCell::new(Vec::new()),
+                _event:    PhantomData,
+            }),
+            grant: Grant::new(None),
+        }
+    }
+
+    pub fn publisher(&self) -> Result<Publisher<'a, E, T>, Revoked> {
+        self.grant.check()?;
+        Ok(Publisher {
+            core:  Rc::clone(&self.core),
+            grant: Grant::new(Some(&self.grant)),
+        })
+    }
+
+    pub fn subscriber(&self) -> Result<Subscriber<'a, E, T>, Revoked> {
+        self.grant.check()?;
+        Ok(Subscriber {
+            core:  Rc::clone(&self.core),
+            grant: Grant::new(Some(&self.grant)),
+        })
+    }
+
+    /// Mints an admin handle that can be revoked without affecting this one.
+    /// Revoking this one revokes it too.
+    pub fn admin(&self) -> Result<Admin<'a, E, T>, Revoked> {
+        self.grant.check()?;
+        Ok(Admin {
+            core:  Rc::clone(&self.core),
+            grant: Grant::new(Some(&self.grant)),
+        })
+    }
+
+    pub fn observers(&self) -> Result<Vec<&'a T>, Revoked> {
+        self.grant.check()?;
+        Ok(self.core.observers.borrow().clone())
+    }
+
+    pub fn len(&self) -> Result<usize, Revoked> {
+        self.grant.check()?;
+        Ok(self.core.observers.borrow().len())
+    }
+
+    pub fn is_empty(&self) -> Result<bool, Revoked> {
+        self.grant.check()?;
+        Ok(self.core.observers.borrow().is_empty())
+    }
+
+    /// Detaches every observer and returns how many there were.
+    pub fn purge(&self) -> Result<usize, Revoked> {
+        self.grant.check()?;
+        Ok(self.core.observers.take().len())
+    }
+}
+
+impl<E, T> Default for Admin<'_, E, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returned by every operation on a revoked handle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Revoked;
+
+impl fmt::Display for Revoked {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "capability has been revoked")
+    }
+}
+
+impl Error for Revoked {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Recorder {
+        seen: RefCell<Vec<u32>>,
+    }
+
+    impl PartialEq for Recorder {
+        fn eq(&self, other: &Self) -> bool {
+            std::ptr::eq(self, other)
+        }
+    }
+
+    impl IEventObserver<u32> for Recorder {
+        fn update(&self, event: &u32) {
+            self.seen.borrow_mut().push(*event);
+        }
+    }
+
+    #[test]
+    fn test_handles_split_the_subject() {
+        let first = Recorder::default();
+        let second = Recorder::default();
+        let admin = Admin::new();
+        let publisher = admin.publisher().unwrap();
+        let subscriber = admin.subscriber().unwrap();
+
+        subscriber.attach(&first).unwrap();
+        subscriber.attach(&second).unwrap();
+        publisher.notify_observers(&1).unwrap();
+        subscriber.detach(&first).unwrap();
+        publisher.notify_observers(&2).unwrap();
+
+        assert_eq!(*first.seen.borrow(), vec![1]);
+        assert_eq!(*second.seen.borrow(), vec![1, 2]);
+        assert_eq!(admin.len(), Ok(1));
+        assert!(std::ptr::eq(admin.observers().unwrap()[0], &second));
+    }
+
+    #[test]
+    fn test_revoking_reaches_clones_only() {
+        let observer = Recorder::default();
+        let admin = Admin::new();
+        let subscriber = admin.subscriber().unwrap();
+        subscriber.attach(&observer).unwrap();
+        let publisher = admin.publisher().unwrap();
+        let handed_out = publisher.clone();
+        let other = admin.publisher().unwrap();
+
+        publisher.revoke();
+
+        assert!(handed_out.is_revoked());
+        assert_eq!(handed_out.notify_observers(&1), Err(Revoked));
+        assert_eq!(other.notify_observers(&2), Ok(()));
+        assert_eq!(*observer.seen.borrow(), vec![2]);
+        // The subscriber kept its own grant.
+        assert_eq!(subscriber.detach(&observer), Ok(()));
+    }
+
+    #[test]
+    fn test_revoked_subscriber_cannot_attach() {
+        let observer = Recorder::default();
+        let admin: Admin<u32, Recorder> = Admin::new();
+        let subscriber = admin.subscriber().unwrap();
+
+        subscriber.revoke();
+
+        assert_eq!(subscriber.attach(&observer), Err(Revoked));
+        assert_eq!(admin.len(), Ok(0));
+    }
+
+    #[test]
+    fn test_admin_purges_and_can_be_revoked() {
+        let observer = Recorder::default();
+        let root: Admin<u32, Recorder> = Admin::new();
+        let delegate = root.admin().unwrap();
+        let subscriber = root.subscriber().unwrap();
+        subscriber.attach(&observer).unwrap();
+        subscriber.attach(&observer).unwrap();
+
+        assert_eq!(delegate.purge(), Ok(2));
+        assert_eq!(root.is_empty(), Ok(true));
+
+        delegate.revoke();
+        assert_eq!(delegate.publisher().err(), Some(Revoked));
+        assert_eq!(delegate.purge(), Err(Revoked));
+        assert!(!root.is_revoked());
+        assert_eq!(Revoked.to_string(), "capability has been revoked");
+    }
+
+    #[test]
+    fn test_revoking_an_admin_reaches_what_it_minted() {
+        let observer = Recorder::default();
+        let root: Admin<u32, Recorder> = Admin::new();
+        let delegate = root.admin().unwrap();
+        let nested = delegate.admin().unwrap();
+        let publisher = nested.publisher().unwrap();
+        let subscriber = root.subscriber().unwrap();
+        subscriber.attach(&observer).unwrap();
+
+        delegate.revoke();
+
+        assert!(publisher.is_revoked());
+        assert_eq!(publisher.notify_observers(&1), Err(Revoked));
+        assert_eq!(nested.purge(), Err(Revoked));
+        assert!(observer.seen.borrow().is_empty());
+        // Handles minted by the root are untouched.
+        assert_eq!(root.publisher().unwrap().notify_observers(&2), Ok(()));
+        assert_eq!(*observer.seen.borrow(), vec![2]);
+    }
+}
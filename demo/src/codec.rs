@@ -0,0 +1,481 @@
+//! Versioned binary encoding for events.
+//!
+//! [`EventCodec`] turns events into self-delimiting frames and back.
+//! [`BinaryCodec`] is the std-only default. It writes one frame per event:
+//!
+//! | bytes | field                                          |
+//! |-------|------------------------------------------------|
+//! | 4     | length of the rest of the frame, big endian    |
+//! | 1     | format version, [`FORMAT_VERSION`]             |
+//! | 4     | type tag, [`Record::TAG`]                      |
+//! | n     | fields                                         |
+//! | 4     | CRC-32 of version, tag and fields              |
+//!
+//! Each field is a 2-byte id, a 4-byte length and the value. Decoders look
+//! fields up by id and skip the ones they do not know, so a record may gain
+//! fields without breaking older readers. Removing a field, or changing what
+//! an id means, still needs a new type tag.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+pub const FORMAT_VERSION: u8 = 1;
+
+/// Largest frame [`BinaryCodec`] writes or accepts, length prefix excluded.
+/// Socket frames in [`ipc`](crate::ipc) share the limit.
+pub const MAX_FRAME_LEN: usize = 16 << 20;
+
+pub trait EventCodec<E> {
+    /// Appends one frame holding `event` to `out`.
+    fn encode(&self, event: &E, out: &mut Vec<u8>) -> Result<(), CodecError>;
+
+    /// Decodes the frame at the start of `bytes`, returning the event and
+    /// the number of bytes the frame took up.
+    fn decode(&self, bytes: &[u8]) -> Result<(E, usize), CodecError>;
+}
+
+/// Events that [`BinaryCodec`] can encode as a set of tagged fields.
+pub trait Record: Sized {
+    /// Identifies the event type on the wire.
+    const TAG: u32;
+
+    fn write_fields(&self, fields: &mut FieldWriter);
+    fn read_fields(fields: &FieldReader) -> Result<Self, CodecError>;
+}
+
+/// Values that can be stored in a field.
+pub trait Field: Sized {
+    fn write(&self, out: &mut Vec<u8>);
+    fn read(bytes: &[u8]) -> Option<Self>;
+}
+
+macro_rules! impl_field_for_number {
+    ($($ty:ty),*) => {$(
+        impl Field for $ty {
+            fn write(&self, out: &mut Vec<u8>) {
+                out.extend_from_slice(&self.to_be_bytes());
+            }
+
+            fn read(bytes: &[u8]) -> Option<Self> {
+                Some(<$ty>::from_be_bytes(bytes.try_into().ok()?))
+            }
+        }
+    )*};
+}
+
+impl_field_for_number!(u8, u16, u32, u64, i32, i64, f64);
+
+impl Field for bool {
+    fn write(&self, out: &mut Vec<u8>) {
+        out.push(u8::from(*self));
+    }
+
+    fn read(bytes: &[u8]) -> Option<Self> {
+        match bytes {
+            [0] => Some(false),
+            [1] => Some(true),
+            _ => None,
+        }
+    }
+}
+
+impl Field for String {
+    fn write(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self.as_bytes());
+    }
+
+    fn read(bytes: &[u8]) -> Option<Self> {
+        String::from_utf8(bytes.to_vec()).ok()
+    }
+}
+
+impl Field for Vec<u8> {
+    fn write(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self);
+    }
+
+    fn read(bytes: &[u8]) -> Option<Self> {
+        Some(bytes.to_vec())
+    }
+}
+
+pub struct FieldWriter {
+    buf: Vec<u8>,
+}
+
+impl FieldWriter {
+    pub fn field<T: Field>(&mut self, id: u16, value: &T) -> &mut Self {
+        self.buf.extend_from_slice(&id.to_be_bytes());
+        let len_at = self.buf.len();
+        self.buf.extend_from_slice(&[0; 4]);
+        value.write(&mut self.buf);
+        let len = (self.buf.len() - len_at - 4) as u32;
+        self.buf[len_at..len_at + 4].copy_from_slice(&len.to_be_bytes());
+        self
+    }
+
+    /// Writes the field only when `value` is `Some`.
+    pub fn optional<T: Field>(
+        &mut self,
+        id: u16,
+        value: &Option<T>,
+    ) -> &mut Self {
+        if let Some(value) = value {
+            self.field(id, value);
+        }
+        self
+    }
+}
+
+pub struct FieldReader<'b> {
+    fields: Vec<(u16, &'b [u8])>,
+}
+
+impl<'b> FieldReader<'b> {
+    fn parse(mut bytes: &'b [u8]) -> Result<FieldReader<'b>, CodecError> {
+        let mut fields = Vec::new();
+        while !bytes.is_empty() {
+            let (id, rest) = take::<2>(bytes).ok_or(CodecError::Malformed)?;
+            let (len, rest) = take::<4>(rest).ok_or(CodecError::Malformed)?;
+            let len = u32::from_be_bytes(len) as usize;
+            if rest.len() < len {
+                return Err(CodecError::Malformed);
+            }
+            fields.push((u16::from_be_bytes(id), &rest[..len]));
+            bytes = &rest[len..];
+        }
+        Ok(FieldReader { fields })
+    }
+
+    /// Reads a field the record cannot do without.
+    pub fn get<T: Field>(&self, id: u16) -> Result<T, CodecError> {
+        self.optional(id)?.ok_or(CodecError::MissingField(id))
+    }
+
+    /// Reads a field that older writers may not have sent.
+    pub fn optional<T: Field>(&self, id: u16) -> Result<Option<T>, CodecError> {
+        match self.fields.iter().find(|(field, _)| *field == id) {
+            Some((_, bytes)) => {
+                T::read(bytes).map(Some).ok_or(CodecError::InvalidField(id))
+            },
+            None => Ok(None),
+        }
+    }
+}
+
+/// Length-prefixed, checksummed frames of [`Record`]s.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BinaryCodec;
+
+impl<E: Record> EventCodec<E> for BinaryCodec {
+    fn encode(&self, event: &E, out: &mut Vec<u8>) -> Result<(), CodecError> {
+        let mut body = FieldWriter {
+            buf: vec![FORMAT_VERSION],
+        };
+        body.buf.extend_from_slice(&E::TAG.to_be_bytes());
+        event.write_fields(&mut body);
+        let mut body = body.buf;
+        body.extend_from_slice(&crc32(&body).to_be_bytes());
+        if body.len() > MAX_FRAME_LEN {
+            return Err(CodecError::TooLarge(body.len()));
+        }
+        out.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        out.extend_from_slice(&body);
+        Ok(())
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<(E, usize), CodecError> {
+        let (len, rest) = take::<4>(bytes).ok_or(CodecError::Truncated)?;
+        let len = u32::from_be_bytes(len) as usize;
+        if len > MAX_FRAME_LEN {
+            return Err(CodecError::TooLarge(len));
+        }
+        // Version, tag and checksum.
+        if len < 9 {
+            return Err(CodecError::Malformed);
+        }
+        if rest.len() < len {
+            return Err(CodecError::Truncated);
+        }
+        let (body, checksum) = rest[..len].split_at(len - 4);
+        let expected = u32::from_be_bytes(checksum.try_into().unwrap());
+        if crc32(body) != expected {
+            return Err(CodecError::Checksum);
+        }
+        if body[0] != FORMAT_VERSION {
+            return Err(CodecError::Version(body[0]));
+        }
+        let tag = u32::from_be_bytes(body[1..5].try_into().unwrap());
+        if tag != E::TAG {
+            return Err(CodecError::UnexpectedTag {
+                expected: E::TAG,
+                found:    tag,
+            });
+        }
+        let event = E::read_fields(&FieldReader::parse(&body[5..])?)?;
+        Ok((event, 4 + len))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodecError {
+    /// The input ends before the frame does.
+    Truncated,
+    /// The frame is longer than [`MAX_FRAME_LEN`].
+    TooLarge(usize),
+    /// The frame's contents do not match its checksum.
+    Checksum,
+    /// The frame was written in a format version this build cannot read.
+    Version(u8),
+    /// The frame holds a different event type.
+    UnexpectedTag {
+        expected: u32,
+        found:    u32,
+    },
+    /// The checksum matched but the frame layout is inconsistent.
+    Malformed,
+    MissingField(u16),
+    InvalidField(u16),
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CodecError::Truncated => write!(f, "frame is truncated"),
+            CodecError::TooLarge(len) => {
+                write!(f, "frame of {len} bytes exceeds {MAX_FRAME_LEN}")
+            },
+            CodecError::Checksum => write!(f, "frame checksum mismatch"),
+            CodecError::Version(version) => {
+                write!(f, "unsupported format version {version}")
+            },
+            CodecError::UnexpectedTag { expected, found } => {
+                write!(f, "expected type tag {expected:#x}, found {found:#x}")
+            },
+            CodecError::Malformed => write!(f, "frame is malformed"),
+            CodecError::MissingField(id) => write!(f, "missing field {id}"),
+            CodecError::InvalidField(id) => write!(f, "invalid field {id}"),
+        }
+    }
+}
+
+impl Error for CodecError {}
+
+impl From<CodecError> for io::Error {
+    fn from(err: CodecError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
+
+/// CRC-32 (IEEE 802.3), as used by zip and PNG.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    const TABLE: [u32; 256] = {
+        let mut table = [0; 256];
+        let mut i = 0;
+        while i < 256 {
+            let mut crc = i as u32;
+            let mut bit = 0;
+            while bit < 8 {
+                crc = if crc & 1 == 1 {
+                    (crc >> 1) ^ 0xEDB8_8320
+                } else {
+                    crc >> 1
+                };
+                bit += 1;
+            }
+            table[i] = crc;
+            i += 1;
+        }
+        table
+    };
+    !bytes.iter().fold(!0, |crc, &byte| {
+        TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
+fn take<const N: usize>(bytes: &[u8]) -> Option<([u8; N], &[u8])> {
+    let (head, rest) = bytes.split_first_chunk::<N>()?;
+    Some((*head, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Transfer {
+        from:   String,
+        to:     String,
+        amount: i64,
+        memo:   Option<String>,
+    }
+
+    impl Record for Transfer {
+        const TAG: u32 = 0x7452_4631;
+
+        fn write_fields(&self, fields: &mut FieldWriter) {
+            fields
+                .field(1, &self.from)
+                .field(2, &self.to)
+                .field(3, &self.amount)
+                .optional(4, &self.memo);
+        }
+
+        fn read_fields(fields: &FieldReader) -> Result<Self, CodecError> {
+            Ok(Transfer {
+                from:   fields.get(1)?,
+                to:     fields.get(2)?,
+                amount: fields.get(3)?,
+                memo:   fields.optional(4)?,
+            })
+        }
+    }
+
+    // A later revision of `Transfer` that added a field.
+    struct TransferV2 {
+        base:     Transfer,
+        approved: bool,
+    }
+
+    impl Record for TransferV2 {
+        const TAG: u32 = Transfer::TAG;
+
+        fn write_fields(&self, fields: &mut FieldWriter) {
+            self.base.write_fields(fields);
+            fields.field(5, &self.approved);
+        }
+
+        fn read_fields(fields: &FieldReader) -> Result<Self, CodecError> {
+            Ok(TransferV2 {
+                base:     Transfer::read_fields(fields)?,
+                approved: fields.get(5)?,
+            })
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Ping;
+
+    impl Record for Ping {
+        const TAG: u32 = 1;
+
+        fn write_fields(&self, _fields: &mut FieldWriter) {}
+
+        fn read_fields(_fields: &FieldReader) -> Result<Self, CodecError> {
+            Ok(Ping)
+        }
+    }
+
+    fn transfer() -> Transfer {
+        Transfer {
+            from:   "alice".to_string(),
+            to:     "bob".to_string(),
+            amount: -1250,
+            memo:   Some("rent".to_string()),
+        }
+    }
+
+    fn encode<E: Record>(event: &E) -> Vec<u8> {
+        let mut out = Vec::new();
+        BinaryCodec.encode(event, &mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut without_memo = transfer();
+        without_memo.memo = None;
+        for event in [transfer(), without_memo] {
+            let bytes = encode(&event);
+            let (decoded, used) =
+                EventCodec::<Transfer>::decode(&BinaryCodec, &bytes).unwrap();
+            assert_eq!(decoded, event);
+            assert_eq!(used, bytes.len());
+        }
+    }
+
+    #[test]
+    fn test_consecutive_frames() {
+        let mut stream = encode(&transfer());
+        stream.extend(encode(&transfer()));
+
+        let (_, first): (Transfer, _) = BinaryCodec.decode(&stream).unwrap();
+        let (second, used): (Transfer, _) =
+            BinaryCodec.decode(&stream[first..]).unwrap();
+
+        assert_eq!(second, transfer());
+        assert_eq!(first + used, stream.len());
+    }
+
+    #[test]
+    fn test_unknown_fields_are_skipped() {
+        let newer = TransferV2 {
+            base:     transfer(),
+            approved: true,
+        };
+        let bytes = encode(&newer);
+
+        let (older, _): (Transfer, _) = BinaryCodec.decode(&bytes).unwrap();
+        assert_eq!(older, transfer());
+
+        // The other way round the new field is missing.
+        let bytes = encode(&transfer());
+        let err = EventCodec::<TransferV2>::decode(&BinaryCodec, &bytes);
+        assert_eq!(err.err(), Some(CodecError::MissingField(5)));
+    }
+
+    #[test]
+    fn test_rejects_every_truncation() {
+        let bytes = encode(&transfer());
+        for len in 0..bytes.len() {
+            let result: Result<(Transfer, _), _> =
+                BinaryCodec.decode(&bytes[..len]);
+            assert_eq!(result.err(), Some(CodecError::Truncated), "len {len}");
+        }
+    }
+
+    #[test]
+    fn test_rejects_every_corrupted_byte() {
+        let bytes = encode(&transfer());
+        // Flips in the length prefix surface as truncation or as a checksum
+        // taken over the wrong bytes; everything else as a checksum mismatch.
+        for at in 0..bytes.len() {
+            for flip in [0x01, 0x80] {
+                let mut corrupt = bytes.clone();
+                corrupt[at] ^= flip;
+                let result: Result<(Transfer, _), _> =
+                    BinaryCodec.decode(&corrupt);
+                assert!(result.is_err(), "byte {at} flipped by {flip:#x}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_rejects_other_versions_and_tags() {
+        let mut bytes = encode(&Ping);
+        bytes[4] = FORMAT_VERSION + 1;
+        let crc = crc32(&bytes[4..bytes.len() - 4]);
+        let at = bytes.len() - 4;
+        bytes[at..].copy_from_slice(&crc.to_be_bytes());
+
+        let result: Result<(Ping, _), _> = BinaryCodec.decode(&bytes);
+        assert_eq!(result.err(), Some(CodecError::Version(FORMAT_VERSION + 1)));
+
+        let result: Result<(Transfer, _), _> =
+            BinaryCodec.decode(&encode(&Ping));
+        assert_eq!(
+            result.err(),
+            Some(CodecError::UnexpectedTag {
+                expected: Transfer::TAG,
+                found:    Ping::TAG,
+            })
+        );
+    }
+
+    #[test]
+    fn test_crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+}
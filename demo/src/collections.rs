@@ -0,0 +1,532 @@
+//! Observable collections emitting structural diffs.
+//!
+//! [`ObservableVec`] and [`ObservableMap`] report each mutation as a
+//! [`VecDiff`] or [`MapDiff`]. Diffs carry the values they displaced, so an
+//! observer can apply them incrementally with `apply_to` and arrive at an
+//! identical copy, or undo them. Mutations that leave the collection
+//! untouched, such as clearing an empty one, emit nothing.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use crate::event::EventSubject;
+use crate::event::IEventObserver;
+use crate::event::IEventSubject;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VecDiff<T> {
+    Insert {
+        index: usize,
+        value: T,
+    },
+    Remove {
+        index: usize,
+        value: T,
+    },
+    Set {
+        index: usize,
+        old:   T,
+        new:   T,
+    },
+    /// The element at `from` was taken out and reinserted at `to`.
+    Move {
+        from: usize,
+        to:   usize,
+    },
+    Clear {
+        removed: Vec<T>,
+    },
+    Replace {
+        old: Vec<T>,
+        new: Vec<T>,
+    },
+}
+
+impl<T: Clone> VecDiff<T> {
+    /// Applies this diff to `target`, which must match the collection's
+    /// contents before the mutation.
+    pub fn apply_to(&self, target: &mut Vec<T>) {
+        match self {
+            VecDiff::Insert { index, value } => {
+                target.insert(*index, value.clone())
+            },
+            VecDiff::Remove { index, .. } => {
+                target.remove(*index);
+            },
+            VecDiff::Set { index, new, .. } => target[*index] = new.clone(),
+            VecDiff::Move { from, to } => {
+                let value = target.remove(*from);
+                target.insert(*to, value);
+            },
+            VecDiff::Clear { .. } => target.clear(),
+            VecDiff::Replace { new, .. } => target.clone_from(new),
+        }
+    }
+}
+
+pub struct ObservableVec<'a, T, O: IEventObserver<VecDiff<T>>> {
+    items:     Vec<T>,
+    observers: EventSubject<'a, VecDiff<T>, O>,
+}
+
+impl<'a, T, O> ObservableVec<'a, T, O>
+where
+    T: Clone,
+    O: IEventObserver<VecDiff<T>> + PartialEq,
+{
+    pub fn new() -> ObservableVec<'a, T, O> {
+        Self::from_vec(Vec::new())
+    }
+
+    pub fn from_vec(items: Vec<T>) -> ObservableVec<'a, T, O> {
+        ObservableVec {
+            items,
+            observers: EventSubject::new(),
+        }
+    }
+
+    pub fn as_slice(&self) -> &[T] {
+        &self.items
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn push(&mut self, value: T) {
+        self.insert(self.items.len(), value);
+    }
+
+    /// # Panics
+    ///
+    /// Panics if `index > len`.
+    pub fn insert(&mut self, index: usize, value: T) {
+        self.items.insert(index, value.clone());
+        self.notify_observers(&VecDiff::Insert { index, value });
+    }
+
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn remove(&mut self, index: usize) -> T {
+        let value = self.items.remove(index);
+        self.notify_observers(&VecDiff::Remove {
+            index,
+            value: value.clone(),
+        });
+        value
+    }
+
+    /// Overwrites the element at `index`, returning the previous one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn set(&mut self, index: usize, value: T) -> T {
+        let old = std::mem::replace(&mut self.items[index], value.clone());
+        self.notify_observers(&VecDiff::Set {
+            index,
+            old: old.clone(),
+            new: value,
+        });
+        old
+    }
+
+    /// Moves the element at `from` so that it ends up at index `to`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either index is out of bounds.
+    pub fn move_item(&mut self, from: usize, to: usize) {
+        assert!(to < self.items.len(), "move target {to} out of bounds");
+        if from == to {
+            return;
+        }
+        let value = self.items.remove(from);
+        self.items.insert(to, value);
+        self.notify_observers(&VecDiff::Move { from, to });
+    }
+
+    pub fn clear(&mut self) {
+        if self.items.is_empty() {
+            return;
+        }
+        let removed = std::mem::take(&mut self.items);
+        self.notify_observers(&VecDiff::Clear { removed });
+    }
+
+    /// Replaces the whole contents in one step.
+    pub fn replace(&mut self, items: Vec<T>) {
+        let old = std::mem::replace(&mut self.items, items);
+        self.notify_observers(&VecDiff::Replace {
+            old,
+            new: self.items.clone(),
+        });
+    }
+}
+
+impl<'a, T, O> Default for ObservableVec<'a, T, O>
+where
+    T: Clone,
+    O: IEventObserver<VecDiff<T>> + PartialEq,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, T, O> IEventSubject<'a, VecDiff<T>, O> for ObservableVec<'a, T, O>
+where
+    T: Clone,
+    O: IEventObserver<VecDiff<T>> + PartialEq,
+{
+    fn attach(&mut self, observer: &'a O) {
+        self.observers.attach(observer);
+    }
+    fn detach(&mut self, observer: &'a O) {
+        self.observers.detach(observer);
+    }
+    fn notify_observers(&self, event: &VecDiff<T>) {
+        self.observers.notify_observers(event);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum MapDiff<K, V> {
+    /// `key` was set to `value`; `old` is what it replaced, if anything.
+    Insert {
+        key:   K,
+        value: V,
+        old:   Option<V>,
+    },
+    Remove {
+        key:   K,
+        value: V,
+    },
+    Clear {
+        removed: HashMap<K, V>,
+    },
+    Replace {
+        old: HashMap<K, V>,
+        new: HashMap<K, V>,
+    },
+}
+
+// Derived `PartialEq` would not carry the `Eq + Hash` bound `HashMap` needs.
+impl<K: Eq + Hash, V: PartialEq> PartialEq for MapDiff<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (
+                MapDiff::Insert { key, value, old },
+                MapDiff::Insert {
+                    key: k,
+                    value: v,
+                    old: o,
+                },
+            ) => key == k && value == v && old == o,
+            (
+                MapDiff::Remove { key, value },
+                MapDiff::Remove { key: k, value: v },
+            ) => key == k && value == v,
+            (MapDiff::Clear { removed }, MapDiff::Clear { removed: r }) => {
+                removed == r
+            },
+            (
+                MapDiff::Replace { old, new },
+                MapDiff::Replace { old: o, new: n },
+            ) => old == o && new == n,
+            _ => false,
+        }
+    }
+}
+
+impl<K: Clone + Eq + Hash, V: Clone> MapDiff<K, V> {
+    /// Applies this diff to `target`, which must match the collection's
+    /// contents before the mutation.
+    pub fn apply_to(&self, target: &mut HashMap<K, V>) {
+        match self {
+            MapDiff::Insert { key, value, .. } => {
+                target.insert(key.clone(), value.clone());
+            },
+            MapDiff::Remove { key, .. } => {
+                target.remove(key);
+            },
+            MapDiff::Clear { .. } => target.clear(),
+            MapDiff::Replace { new, .. } => target.clone_from(new),
+        }
+    }
+}
+
+pub struct ObservableMap<'a, K, V, O: IEventObserver<MapDiff<K, V>>> {
+    entries:   HashMap<K, V>,
+    observers: EventSubject<'a, MapDiff<K, V>, O>,
+}
+
+impl<'a, K, V, O> ObservableMap<'a, K, V, O>
+where
+    K: Clone + Eq + Hash,
+    V: Clone,
+    O: IEventObserver<MapDiff<K, V>> + PartialEq,
+{
+    pub fn new() -> ObservableMap<'a, K, V, O> {
+        Self::from_map(HashMap::new())
+    }
+
+    pub fn from_map(entries: HashMap<K, V>) -> ObservableMap<'a, K, V, O> {
+        ObservableMap {
+            entries,
+            observers: EventSubject::new(),
+        }
+    }
+
+    pub fn as_map(&self) -> &HashMap<K, V> {
+        &self.entries
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.entries.get(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Inserts or overwrites `key`, returning the previous value.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let old = self.entries.insert(key.clone(), value.clone());
+        self.notify_observers(&MapDiff::Insert {
+            key,
+            value,
+            old: old.clone(),
+        });
+        old
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let value = self.entries.remove(key)?;
+        self.notify_observers(&MapDiff::Remove {
+            key:   key.clone(),
+            value: value.clone(),
+        });
+        Some(value)
+    }
+
+    pub fn clear(&mut self) {
+        if self.entries.is_empty() {
+            return;
+        }
+        let removed = std::mem::take(&mut self.entries);
+        self.notify_observers(&MapDiff::Clear { removed });
+    }
+
+    /// Replaces every entry in one step.
+    pub fn replace(&mut self, entries: HashMap<K, V>) {
+        let old = std::mem::replace(&mut self.entries, entries);
+        self.notify_observers(&MapDiff::Replace {
+            old,
+            new: self.entries.clone(),
+        });
+    }
+}
+
+impl<'a, K, V, O> Default for ObservableMap<'a, K, V, O>
+where
+    K: Clone + Eq + Hash,
+    V: Clone,
+    O: IEventObserver<MapDiff<K, V>> + PartialEq,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, K, V, O> IEventSubject<'a, MapDiff<K, V>, O>
+    for ObservableMap<'a, K, V, O>
+where
+    K: Clone + Eq + Hash,
+    V: Clone,
+    O: IEventObserver<MapDiff<K, V>> + PartialEq,
+{
+    fn attach(&mut self, observer: &'a O) {
+        self.observers.attach(observer);
+    }
+    fn detach(&mut self, observer: &'a O) {
+        self.observers.detach(observer);
+    }
+    fn notify_observers(&self, event: &MapDiff<K, V>) {
+        self.observers.notify_observers(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::rng::Rng;
+
+    // Rebuilds its own copy of the collection purely from diffs.
+    #[derive(PartialEq, Default)]
+    struct VecMirror {
+        copy: RefCell<Vec<u32>>,
+    }
+
+    impl IEventObserver<VecDiff<u32>> for VecMirror {
+        fn update(&self, event: &VecDiff<u32>) {
+            event.apply_to(&mut self.copy.borrow_mut());
+        }
+    }
+
+    #[derive(PartialEq, Default)]
+    struct MapMirror {
+        copy: RefCell<HashMap<u32, u32>>,
+    }
+
+    impl IEventObserver<MapDiff<u32, u32>> for MapMirror {
+        fn update(&self, event: &MapDiff<u32, u32>) {
+            event.apply_to(&mut self.copy.borrow_mut());
+        }
+    }
+
+    #[test]
+    fn test_vec_diffs() {
+        let seen = RefCell::new(Vec::new());
+        struct Log<'l>(&'l RefCell<Vec<VecDiff<u32>>>);
+        impl PartialEq for Log<'_> {
+            fn eq(&self, other: &Self) -> bool {
+                std::ptr::eq(self, other)
+            }
+        }
+        impl IEventObserver<VecDiff<u32>> for Log<'_> {
+            fn update(&self, event: &VecDiff<u32>) {
+                self.0.borrow_mut().push(event.clone());
+            }
+        }
+        let log = Log(&seen);
+        let mut list = ObservableVec::new();
+        list.attach(&log);
+
+        list.push(1);
+        list.push(2);
+        list.move_item(0, 1);
+        list.set(0, 5);
+        list.remove(1);
+        list.clear();
+        list.clear();
+
+        assert_eq!(
+            *seen.borrow(),
+            vec![
+                VecDiff::Insert { index: 0, value: 1 },
+                VecDiff::Insert { index: 1, value: 2 },
+                VecDiff::Move { from: 0, to: 1 },
+                VecDiff::Set {
+                    index: 0,
+                    old:   2,
+                    new:   5,
+                },
+                VecDiff::Remove { index: 1, value: 1 },
+                VecDiff::Clear { removed: vec![5] },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_map_remove_missing_key_is_silent() {
+        let observer = MapMirror::default();
+        let mut map = ObservableMap::new();
+        map.attach(&observer);
+
+        assert_eq!(map.insert(1, 10), None);
+        assert_eq!(map.insert(1, 11), Some(10));
+        assert_eq!(map.remove(&2), None);
+
+        assert_eq!(*observer.copy.borrow(), HashMap::from([(1, 11)]));
+    }
+
+    #[test]
+    fn test_property_vec_mirror_matches() {
+        // Odd seeds only: `seeded` sets the low bit.
+        for seed in (1..400).step_by(2) {
+            let rng = Rng::seeded(seed);
+            let observer = VecMirror::default();
+            let mut list = ObservableVec::new();
+            list.attach(&observer);
+
+            for _ in 0..100 {
+                let len = list.len();
+                match rng.below(7) {
+                    0 | 1 => {
+                        list.insert(rng.below(len + 1), rng.next_u64() as u32)
+                    },
+                    2 if len > 0 => {
+                        list.remove(rng.below(len));
+                    },
+                    3 if len > 0 => {
+                        list.set(rng.below(len), rng.next_u64() as u32);
+                    },
+                    4 if len > 0 => {
+                        list.move_item(rng.below(len), rng.below(len))
+                    },
+                    5 if rng.below(10) == 0 => list.clear(),
+                    6 if rng.below(10) == 0 => {
+                        let items =
+                            (0..rng.below(8)).map(|i| i as u32).collect();
+                        list.replace(items);
+                    },
+                    _ => list.push(rng.next_u64() as u32),
+                }
+                assert_eq!(
+                    observer.copy.borrow().as_slice(),
+                    list.as_slice(),
+                    "diverged with seed {seed}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_property_map_mirror_matches() {
+        // Odd seeds only: `seeded` sets the low bit.
+        for seed in (1..400).step_by(2) {
+            let rng = Rng::seeded(seed);
+            let observer = MapMirror::default();
+            let mut map = ObservableMap::new();
+            map.attach(&observer);
+
+            for _ in 0..100 {
+                // A small key space makes overwrites and removals common.
+                let key = rng.below(16) as u32;
+                match rng.below(6) {
+                    0..=2 => {
+                        map.insert(key, rng.next_u64() as u32);
+                    },
+                    3 => {
+                        map.remove(&key);
+                    },
+                    4 if rng.below(10) == 0 => map.clear(),
+                    5 if rng.below(10) == 0 => {
+                        let entries = (0..rng.below(8) as u32)
+                            .map(|k| (k, k * 2))
+                            .collect();
+                        map.replace(entries);
+                    },
+                    _ => {},
+                }
+                assert_eq!(
+                    *observer.copy.borrow(),
+                    *map.as_map(),
+                    "diverged with seed {seed}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,319 @@
+//! Event envelopes carrying delivery metadata.
+//!
+//! A [`TracedSubject`] wraps every payload it publishes in an [`Envelope`]
+//! recording a per-subject sequence number, the wall-clock time, the
+//! publishing subject and, optionally, a correlation ID for the whole flow.
+//!
+//! While an envelope is being delivered it is tracked in a thread-local
+//! context. Any traced subject that publishes from inside an observer's
+//! `update` on the same thread records that envelope as the new event's
+//! cause and inherits its correlation ID, so causation chains build
+//! themselves without observers having to pass IDs around.
+
+use std::cell::RefCell;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::time::SystemTime;
+
+use crate::event::EventSubject;
+use crate::event::IEventObserver;
+use crate::event::IEventSubject;
+
+/// Process-unique identifier of a [`TracedSubject`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SubjectId(u64);
+
+impl SubjectId {
+    fn next() -> SubjectId {
+        static NEXT: AtomicU64 = AtomicU64::new(1);
+        SubjectId(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+
+    pub fn get(self) -> u64 {
+        self.0
+    }
+}
+
+/// Identifies one event: the subject that published it and its sequence
+/// number there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EventId {
+    pub source:   SubjectId,
+    pub sequence: u64,
+}
+
+/// Caller-chosen identifier tying together every event of one flow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CorrelationId(pub u64);
+
+#[derive(Debug, Clone)]
+pub struct Envelope<E> {
+    /// Starts at 1 and increases by one per event of the source subject.
+    pub sequence:    u64,
+    pub timestamp:   SystemTime,
+    pub source:      SubjectId,
+    pub correlation: Option<CorrelationId>,
+    /// The event whose delivery triggered this one, if any.
+    pub causation:   Option<EventId>,
+    pub payload:     E,
+}
+
+impl<E> Envelope<E> {
+    pub fn id(&self) -> EventId {
+        EventId {
+            source:   self.source,
+            sequence: self.sequence,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Cause {
+    event: EventId,
+    correlation: Option<CorrelationId>,
+}
+
+thread_local! {
+    // Envelopes currently being delivered on this thread, innermost last.
+    static DELIVERING: RefCell<Vec<Cause>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Returns the event being delivered on this thread, if called from within
+/// an observer of a [`TracedSubject`].
+pub fn current_event() -> Option<EventId> {
+    current_cause().map(|cause| cause.event)
+}
+
+fn current_cause() -> Option<Cause> {
+    DELIVERING.with(|stack| stack.borrow().last().copied())
+}
+
+// Keeps an envelope on the delivery stack for as long as it lives, so the
+// stack unwinds correctly even if an observer panics.
+struct DeliveryScope;
+
+impl DeliveryScope {
+    fn enter(cause: Cause) -> DeliveryScope {
+        DELIVERING.with(|stack| stack.borrow_mut().push(cause));
+        DeliveryScope
+    }
+}
+
+impl Drop for DeliveryScope {
+    fn drop(&mut self) {
+        DELIVERING.with(|stack| stack.borrow_mut().pop());
+    }
+}
+
+pub struct TracedSubject<'a, E, T: IEventObserver<Envelope<E>>> {
+    id: SubjectId,
+    sequence: AtomicU64,
+    observers: EventSubject<'a, Envelope<E>, T>,
+}
+
+impl<'a, E, T: IEventObserver<Envelope<E>> + PartialEq>
+    TracedSubject<'a, E, T>
+{
+    pub fn new() -> TracedSubject<'a, E, T> {
+        TracedSubject {
+            id: SubjectId::next(),
+            sequence: AtomicU64::new(0),
+            observers: EventSubject::new(),
+        }
+    }
+
+    pub fn id(&self) -> SubjectId {
+        self.id
+    }
+
+    pub fn attach(&mut self, observer: &'a T) {
+        self.observers.attach(observer);
+    }
+
+    pub fn detach(&mut self, observer: &'a T) {
+        self.observers.detach(observer);
+    }
+
+    /// Wraps `payload` in an envelope and delivers it to every observer.
+    ///
+    /// The correlation ID is inherited from the event being delivered on
+    /// this thread, if any.
+    pub fn notify_observers(&self, payload: E) -> EventId {
+        self.publish(payload, None)
+    }
+
+    /// Like [`notify_observers`](Self::notify_observers), but starts a new
+    /// flow under `correlation` instead of inheriting one.
+    pub fn notify_correlated(
+        &self,
+        payload: E,
+        correlation: CorrelationId,
+    ) -> EventId {
+        self.publish(payload, Some(correlation))
+    }
+
+    fn publish(
+        &self,
+        payload: E,
+        correlation: Option<CorrelationId>,
+    ) -> EventId {
+        let cause = current_cause();
+        let envelope = Envelope {
+            sequence: self.sequence.fetch_add(1, Ordering::Relaxed) + 1,
+            timestamp: SystemTime::now(),
+            source: self.id,
+            correlation: correlation.or(cause.and_then(|c| c.correlation)),
+            causation: cause.map(|c| c.event),
+            payload,
+        };
+        let _scope = DeliveryScope::enter(Cause {
+            event: envelope.id(),
+            correlation: envelope.correlation,
+        });
+        self.observers.notify_observers(&envelope);
+        envelope.id()
+    }
+}
+
+impl<'a, E, T: IEventObserver<Envelope<E>> + PartialEq> Default
+    for TracedSubject<'a, E, T>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ptr;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct Recorder {
+        seen: RefCell<Vec<Envelope<u32>>>,
+    }
+
+    impl PartialEq for Recorder {
+        fn eq(&self, other: &Self) -> bool {
+            ptr::eq(self, other)
+        }
+    }
+
+    impl IEventObserver<Envelope<u32>> for Recorder {
+        fn update(&self, event: &Envelope<u32>) {
+            self.seen.borrow_mut().push(event.clone());
+        }
+    }
+
+    // Records what it sees, then publishes `payload + 1` downstream from
+    // inside its own update.
+    struct Relay<'s, 'a, T: IEventObserver<Envelope<u32>>> {
+        seen: Recorder,
+        downstream: &'s TracedSubject<'a, u32, T>,
+    }
+
+    impl<T: IEventObserver<Envelope<u32>>> PartialEq for Relay<'_, '_, T> {
+        fn eq(&self, other: &Self) -> bool {
+            ptr::eq(self, other)
+        }
+    }
+
+    impl<T: IEventObserver<Envelope<u32>> + PartialEq>
+        IEventObserver<Envelope<u32>> for Relay<'_, '_, T>
+    {
+        fn update(&self, event: &Envelope<u32>) {
+            self.seen.update(event);
+            self.downstream.notify_observers(event.payload + 1);
+        }
+    }
+
+    #[test]
+    fn test_sequence_and_source() {
+        let observer = Recorder::default();
+        let mut subject = TracedSubject::new();
+        subject.attach(&observer);
+
+        subject.notify_observers(10);
+        subject.notify_observers(20);
+        subject.notify_observers(30);
+
+        let seen = observer.seen.borrow();
+        let sequences: Vec<_> = seen.iter().map(|e| e.sequence).collect();
+        assert_eq!(sequences, vec![1, 2, 3]);
+        assert!(seen.iter().all(|e| e.source == subject.id()));
+        assert!(seen.windows(2).all(|w| w[0].timestamp <= w[1].timestamp));
+        assert!(seen.iter().all(|e| e.causation.is_none()));
+        assert_eq!(seen[1].payload, 20);
+    }
+
+    #[test]
+    fn test_subjects_have_distinct_ids() {
+        let a: TracedSubject<'_, u32, Recorder> = TracedSubject::new();
+        let b: TracedSubject<'_, u32, Recorder> = TracedSubject::new();
+
+        assert_ne!(a.id(), b.id());
+    }
+
+    #[test]
+    fn test_nested_notifications_inherit_causation() {
+        let leaf = Recorder::default();
+        let mut c = TracedSubject::new();
+        c.attach(&leaf);
+        let relay_bc = Relay {
+            seen: Recorder::default(),
+            downstream: &c,
+        };
+        let mut b = TracedSubject::new();
+        b.attach(&relay_bc);
+        let relay_ab = Relay {
+            seen: Recorder::default(),
+            downstream: &b,
+        };
+        let mut a = TracedSubject::new();
+        a.attach(&relay_ab);
+
+        let root = a.notify_correlated(1, CorrelationId(42));
+
+        let at_b = relay_bc.seen.seen.borrow()[0].clone();
+        let at_c = leaf.seen.borrow()[0].clone();
+        assert_eq!(at_b.causation, Some(root));
+        assert_eq!(at_c.causation, Some(at_b.id()));
+        assert_eq!(at_b.correlation, Some(CorrelationId(42)));
+        assert_eq!(at_c.correlation, Some(CorrelationId(42)));
+        assert_eq!(at_c.payload, 3);
+        assert_eq!(current_event(), None);
+    }
+
+    #[test]
+    fn test_explicit_correlation_overrides_inherited() {
+        let leaf = Recorder::default();
+        let mut b = TracedSubject::new();
+        b.attach(&leaf);
+
+        struct Restarter<'s, 'a> {
+            downstream: &'s TracedSubject<'a, u32, Recorder>,
+        }
+        impl PartialEq for Restarter<'_, '_> {
+            fn eq(&self, other: &Self) -> bool {
+                ptr::eq(self, other)
+            }
+        }
+        impl IEventObserver<Envelope<u32>> for Restarter<'_, '_> {
+            fn update(&self, event: &Envelope<u32>) {
+                assert_eq!(current_event(), Some(event.id()));
+                self.downstream
+                    .notify_correlated(event.payload, CorrelationId(7));
+            }
+        }
+
+        let restarter = Restarter { downstream: &b };
+        let mut a = TracedSubject::new();
+        a.attach(&restarter);
+        let root = a.notify_correlated(5, CorrelationId(1));
+
+        let at_b = leaf.seen.borrow()[0].clone();
+        assert_eq!(at_b.correlation, Some(CorrelationId(7)));
+        assert_eq!(at_b.causation, Some(root));
+    }
+}
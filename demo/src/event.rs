@@ -0,0 +1,153 @@
+//! Observer pattern variant that carries a payload with every notification.
+//!
+//! [`IObserver::update`](crate::observer::IObserver::update) only says that
+//! *something* happened. [`IEventObserver`] receives the event itself, and
+//! [`EventSubject`] mirrors [`Subject`](crate::observer::Subject) otherwise.
+
+use std::error::Error;
+use std::fmt;
+use std::marker::PhantomData;
+
+pub trait IEventObserver<E> {
+    fn update(&self, event: &E);
+}
+
+/// Error an observer reports when it could not handle an event.
+pub type ObserverError = Box<dyn Error + Send + Sync>;
+
+/// Observer whose `update` can fail, for subjects that react to failures by
+/// retrying, skipping or parking the event.
+pub trait IFallibleObserver<E> {
+    fn try_update(&self, event: &E) -> Result<(), ObserverError>;
+}
+
+/// Observers that cannot fail are fallible observers that always succeed.
+impl<E, T: IEventObserver<E> + ?Sized> IFallibleObserver<E> for T {
+    fn try_update(&self, event: &E) -> Result<(), ObserverError> {
+        self.update(event);
+        Ok(())
+    }
+}
+
+/// Identifies one attachment to a subject that hands out IDs; never reused
+/// by the same subject.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ObserverId(pub u64);
+
+impl fmt::Display for ObserverId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+pub trait IEventSubject<'a, E, T: IEventObserver<E>> {
+    fn attach(&mut self, observer: &'a T);
+    fn detach(&mut self, observer: &'a T);
+    fn notify_observers(&self, event: &E);
+}
+
+pub struct EventSubject<'a, E, T: IEventObserver<E>> {
+    observers: Vec<&'a T>,
+    _event:    PhantomData<fn(&E)>,
+}
+impl<'a, E, T: IEventObserver<E> + PartialEq> EventSubject<'a, E, T> {
+    pub fn new() -> EventSubject<'a, E, T> {
+        EventSubject {
+            observers: Vec::new(),
+            _event:    PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.observers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.observers.is_empty()
+    }
+}
+
+impl<'a, E, T: IEventObserver<E> + PartialEq> Default
+    for EventSubject<'a, E, T>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, E, T: IEventObserver<E> + PartialEq> IEventSubject<'a, E, T>
+    for EventSubject<'a, E, T>
+{
+    fn attach(&mut self, observer: &'a T) {
+        self.observers.push(observer);
+    }
+    fn detach(&mut self, observer: &'a T) {
+        if let Some(idx) = self.observers.iter().position(|x| *x == observer) {
+            self.observers.remove(idx);
+        }
+    }
+    fn notify_observers(&self, event: &E) {
+        for item in self.observers.iter() {
+            item.update(event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+
+    // Records every event it receives.
+    #[derive(PartialEq)]
+    struct RecordingObserver {
+        id: i32,
+        received: RefCell<Vec<String>>,
+    }
+
+    impl RecordingObserver {
+        fn new(id: i32) -> Self {
+            Self {
+                id,
+                received: RefCell::new(Vec::new()),
+            }
+        }
+    }
+
+    impl IEventObserver<String> for RecordingObserver {
+        fn update(&self, event: &String) {
+            self.received.borrow_mut().push(event.clone());
+        }
+    }
+
+    #[test]
+    fn test_notify_delivers_payload() {
+        let mut subject = EventSubject::new();
+        let observer1 = RecordingObserver::new(1);
+        let observer2 = RecordingObserver::new(2);
+
+        subject.attach(&observer1);
+        subject.attach(&observer2);
+        subject.notify_observers(&"hello".to_string());
+
+        assert_eq!(*observer1.received.borrow(), vec!["hello"]);
+        assert_eq!(*observer2.received.borrow(), vec!["hello"]);
+    }
+
+    #[test]
+    fn test_detach() {
+        let mut subject = EventSubject::new();
+        let observer1 = RecordingObserver::new(1);
+        let observer2 = RecordingObserver::new(2);
+
+        subject.attach(&observer1);
+        subject.attach(&observer2);
+        subject.detach(&observer1);
+        subject.notify_observers(&"hello".to_string());
+
+        assert!(observer1.received.borrow().is_empty());
+        assert_eq!(*observer2.received.borrow(), vec!["hello"]);
+        assert_eq!(subject.len(), 1);
+    }
+}
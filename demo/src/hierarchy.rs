@@ -0,0 +1,428 @@
+//! Subjects arranged in a tree, with DOM-style event propagation.
+//!
+//! A [`SubjectTree`] holds nodes in a parent/child hierarchy. Dispatching an
+//! event at a node routes it in three phases:
+//!
+//! 1. **Capture**: capture observers on each ancestor, root first.
+//! 2. **Target**: capture then bubble observers on the target node itself.
+//! 3. **Bubble**: bubble observers on each ancestor, parent first.
+//!
+//! Observers receive a [`Routed`] event exposing the original target, the
+//! node currently handling it and the phase. Calling
+//! [`stop_propagation`](Routed::stop_propagation) lets the remaining observers
+//! of the current node run and then stops;
+//! [`stop_immediate_propagation`](Routed::stop_immediate_propagation) stops
+//! right away.
+
+use std::cell::Cell;
+use std::marker::PhantomData;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+
+use crate::event::IEventObserver;
+
+/// Handle of a node in a [`SubjectTree`].
+///
+/// Only meaningful to the tree that returned it; methods taking a `NodeId`
+/// from another tree panic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId {
+    tree:  u64,
+    index: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Capture,
+    Target,
+    Bubble,
+}
+
+/// An event on its way through a [`SubjectTree`].
+pub struct Routed<E> {
+    payload:   E,
+    target:    NodeId,
+    current:   Cell<NodeId>,
+    phase:     Cell<Phase>,
+    stopped:   Cell<bool>,
+    immediate: Cell<bool>,
+}
+
+impl<E> Routed<E> {
+    pub fn payload(&self) -> &E {
+        &self.payload
+    }
+
+    /// Node the event was dispatched at.
+    pub fn target(&self) -> NodeId {
+        self.target
+    }
+
+    /// Node whose observers are being notified right now.
+    pub fn current_target(&self) -> NodeId {
+        self.current.get()
+    }
+
+    pub fn phase(&self) -> Phase {
+        self.phase.get()
+    }
+
+    /// Stops the event once the current node's observers have run.
+    pub fn stop_propagation(&self) {
+        self.stopped.set(true);
+    }
+
+    /// Stops the event before any further observer runs.
+    pub fn stop_immediate_propagation(&self) {
+        self.stopped.set(true);
+        self.immediate.set(true);
+    }
+
+    pub fn is_propagation_stopped(&self) -> bool {
+        self.stopped.get()
+    }
+
+    pub fn into_payload(self) -> E {
+        self.payload
+    }
+}
+
+struct Node<'a, T> {
+    parent:  Option<NodeId>,
+    capture: Vec<&'a T>,
+    bubble:  Vec<&'a T>,
+}
+
+pub struct SubjectTree<'a, E, T: IEventObserver<Routed<E>>> {
+    /// Process-unique, so nodes of other trees are told apart.
+    id:     u64,
+    nodes:  Vec<Node<'a, T>>,
+    _event: PhantomData<fn(&E)>,
+}
+
+impl<'a, E, T: IEventObserver<Routed<E>> + PartialEq> SubjectTree<'a, E, T> {
+    pub fn new() -> SubjectTree<'a, E, T> {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        SubjectTree {
+            id:     NEXT.fetch_add(1, Ordering::Relaxed),
+            nodes:  Vec::new(),
+            _event: PhantomData,
+        }
+    }
+
+    pub fn add_root(&mut self) -> NodeId {
+        self.push(None)
+    }
+
+    /// # Panics
+    ///
+    /// Panics if `parent` does not belong to this tree.
+    pub fn add_child(&mut self, parent: NodeId) -> NodeId {
+        self.check(parent);
+        self.push(Some(parent))
+    }
+
+    /// # Panics
+    ///
+    /// Panics if `node` does not belong to this tree.
+    pub fn parent(&self, node: NodeId) -> Option<NodeId> {
+        self.check(node);
+        self.nodes[node.index].parent
+    }
+
+    /// Attaches an observer for the target and bubble phases at `node`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `node` does not belong to this tree.
+    pub fn attach(&mut self, node: NodeId, observer: &'a T) {
+        self.check(node);
+        self.nodes[node.index].bubble.push(observer);
+    }
+
+    /// Attaches an observer for the capture and target phases at `node`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `node` does not belong to this tree.
+    pub fn attach_capture(&mut self, node: NodeId, observer: &'a T) {
+        self.check(node);
+        self.nodes[node.index].capture.push(observer);
+    }
+
+    /// Removes `observer` from both phases at `node`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `node` does not belong to this tree.
+    pub fn detach(&mut self, node: NodeId, observer: &'a T) {
+        self.check(node);
+        let node = &mut self.nodes[node.index];
+        for list in [&mut node.capture, &mut node.bubble] {
+            if let Some(idx) = list.iter().position(|x| *x == observer) {
+                list.remove(idx);
+            }
+        }
+    }
+
+    /// Routes `payload` through the tree towards `target` and back, and
+    /// returns the event so callers can inspect whether it was stopped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `target` does not belong to this tree.
+    pub fn dispatch(&self, target: NodeId, payload: E) -> Routed<E> {
+        self.check(target);
+        let event = Routed {
+            payload,
+            target,
+            current: Cell::new(target),
+            phase: Cell::new(Phase::Capture),
+            stopped: Cell::new(false),
+            immediate: Cell::new(false),
+        };
+        let mut ancestors = Vec::new();
+        let mut node = self.parent(target);
+        while let Some(id) = node {
+            ancestors.push(id);
+            node = self.parent(id);
+        }
+
+        let route =
+            ancestors
+                .iter()
+                .rev()
+                .map(|&id| (id, Phase::Capture, &self.nodes[id.index].capture))
+                .chain([
+                    (target, Phase::Target, &self.nodes[target.index].capture),
+                    (target, Phase::Target, &self.nodes[target.index].bubble),
+                ])
+                .chain(ancestors.iter().map(|&id| {
+                    (id, Phase::Bubble, &self.nodes[id.index].bubble)
+                }));
+        for (id, phase, observers) in route {
+            // `stop_propagation` only takes effect between nodes.
+            if event.stopped.get() && id != event.current.get() {
+                break;
+            }
+            event.current.set(id);
+            event.phase.set(phase);
+            for item in observers {
+                if event.immediate.get() {
+                    return event;
+                }
+                item.update(&event);
+            }
+        }
+        event
+    }
+
+    fn check(&self, node: NodeId) {
+        assert!(
+            node.tree == self.id && node.index < self.nodes.len(),
+            "{node:?} does not belong to tree {}",
+            self.id
+        );
+    }
+
+    fn push(&mut self, parent: Option<NodeId>) -> NodeId {
+        self.nodes.push(Node {
+            parent,
+            capture: Vec::new(),
+            bubble: Vec::new(),
+        });
+        NodeId {
+            tree:  self.id,
+            index: self.nodes.len() - 1,
+        }
+    }
+}
+
+impl<'a, E, T: IEventObserver<Routed<E>> + PartialEq> Default
+    for SubjectTree<'a, E, T>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+
+    #[derive(Clone, Copy)]
+    enum Action {
+        Continue,
+        Stop,
+        StopImmediate,
+    }
+
+    // Logs "<name> <phase>" and optionally stops the event.
+    struct Listener<'l> {
+        name:   &'static str,
+        action: Action,
+        log:    &'l RefCell<Vec<String>>,
+        seen:   RefCell<Vec<NodeId>>,
+    }
+
+    impl<'l> Listener<'l> {
+        fn new(name: &'static str, log: &'l RefCell<Vec<String>>) -> Self {
+            Self::with_action(name, Action::Continue, log)
+        }
+
+        fn with_action(
+            name: &'static str,
+            action: Action,
+            log: &'l RefCell<Vec<String>>,
+        ) -> Self {
+            Listener {
+                name,
+                action,
+                log,
+                seen: RefCell::new(Vec::new()),
+            }
+        }
+    }
+
+    impl PartialEq for Listener<'_> {
+        fn eq(&self, other: &Self) -> bool {
+            std::ptr::eq(self, other)
+        }
+    }
+
+    impl IEventObserver<Routed<&'static str>> for Listener<'_> {
+        fn update(&self, event: &Routed<&'static str>) {
+            self.log.borrow_mut().push(format!(
+                "{} {:?}",
+                self.name,
+                event.phase()
+            ));
+            self.seen.borrow_mut().push(event.target());
+            match self.action {
+                Action::Continue => {},
+                Action::Stop => event.stop_propagation(),
+                Action::StopImmediate => event.stop_immediate_propagation(),
+            }
+        }
+    }
+
+    #[test]
+    fn test_capture_target_bubble_order() {
+        let log = RefCell::new(Vec::new());
+        let root_cap = Listener::new("root", &log);
+        let root_bub = Listener::new("root", &log);
+        let mid_cap = Listener::new("mid", &log);
+        let mid_bub = Listener::new("mid", &log);
+        let leaf = Listener::new("leaf", &log);
+        let mut tree = SubjectTree::new();
+        let root = tree.add_root();
+        let mid = tree.add_child(root);
+        let target = tree.add_child(mid);
+        tree.attach_capture(root, &root_cap);
+        tree.attach(root, &root_bub);
+        tree.attach_capture(mid, &mid_cap);
+        tree.attach(mid, &mid_bub);
+        tree.attach(target, &leaf);
+
+        let event = tree.dispatch(target, "click");
+
+        assert_eq!(
+            *log.borrow(),
+            vec![
+                "root Capture",
+                "mid Capture",
+                "leaf Target",
+                "mid Bubble",
+                "root Bubble",
+            ]
+        );
+        assert!(!event.is_propagation_stopped());
+        // Every level saw the original target.
+        assert_eq!(*root_bub.seen.borrow(), vec![target]);
+        assert_eq!(*mid_cap.seen.borrow(), vec![target]);
+    }
+
+    #[test]
+    fn test_stop_during_capture() {
+        let log = RefCell::new(Vec::new());
+        let guard = Listener::with_action("guard", Action::Stop, &log);
+        let leaf = Listener::new("leaf", &log);
+        let mut tree = SubjectTree::new();
+        let root = tree.add_root();
+        let target = tree.add_child(root);
+        tree.attach_capture(root, &guard);
+        tree.attach(target, &leaf);
+
+        let event = tree.dispatch(target, "click");
+
+        assert!(event.is_propagation_stopped());
+        assert_eq!(*log.borrow(), vec!["guard Capture"]);
+    }
+
+    #[test]
+    fn test_stop_lets_same_node_finish() {
+        let log = RefCell::new(Vec::new());
+        let first = Listener::with_action("first", Action::Stop, &log);
+        let second = Listener::new("second", &log);
+        let parent = Listener::new("parent", &log);
+        let mut tree = SubjectTree::new();
+        let root = tree.add_root();
+        let target = tree.add_child(root);
+        tree.attach(target, &first);
+        tree.attach(target, &second);
+        tree.attach(root, &parent);
+
+        tree.dispatch(target, "click");
+
+        assert_eq!(*log.borrow(), vec!["first Target", "second Target"]);
+    }
+
+    #[test]
+    fn test_stop_immediate() {
+        let log = RefCell::new(Vec::new());
+        let first = Listener::with_action("first", Action::StopImmediate, &log);
+        let second = Listener::new("second", &log);
+        let mut tree = SubjectTree::new();
+        let target = tree.add_root();
+        tree.attach(target, &first);
+        tree.attach(target, &second);
+
+        tree.dispatch(target, "click");
+
+        assert_eq!(*log.borrow(), vec!["first Target"]);
+    }
+
+    #[test]
+    fn test_detach_and_sibling_isolation() {
+        let log = RefCell::new(Vec::new());
+        let parent = Listener::new("parent", &log);
+        let sibling = Listener::new("sibling", &log);
+        let mut tree = SubjectTree::new();
+        let root = tree.add_root();
+        let left = tree.add_child(root);
+        let right = tree.add_child(root);
+        tree.attach(root, &parent);
+        tree.attach(right, &sibling);
+
+        tree.dispatch(left, "click");
+        tree.detach(root, &parent);
+        tree.dispatch(left, "click");
+
+        assert_eq!(*log.borrow(), vec!["parent Bubble"]);
+        assert_eq!(tree.parent(left), Some(root));
+    }
+
+    #[test]
+    #[should_panic(expected = "does not belong to tree")]
+    fn test_foreign_node_panics() {
+        let mut other: SubjectTree<&'static str, Listener> = SubjectTree::new();
+        let foreign = other.add_root();
+        let mut tree: SubjectTree<&'static str, Listener> = SubjectTree::new();
+        tree.add_root();
+
+        // Index 0 exists here too, but belongs to another tree.
+        tree.dispatch(foreign, "click");
+    }
+}
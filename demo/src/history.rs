@@ -0,0 +1,502 @@
+//! Undo/redo history driven by change events.
+//!
+//! A [`History`] is attached as an observer to an [`Observable`],
+//! [`ObservableVec`] or [`ObservableMap`] and records every change it is
+//! told about. [`History::undo`] and [`History::redo`] replay the inverse or
+//! original changes through the target's normal mutation methods, so the
+//! target notifies all of its observers just as for an ordinary edit; the
+//! history ignores those replayed events itself.
+//!
+//! Edits that arrive within the coalescing window of the previous one are
+//! merged into a single undo step, and the number of retained steps is
+//! bounded.
+
+use std::cell::Cell;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::hash::Hash;
+use std::time::Duration;
+
+use crate::clock::Clock;
+use crate::clock::SystemClock;
+use crate::collections::MapDiff;
+use crate::collections::ObservableMap;
+use crate::collections::ObservableVec;
+use crate::collections::VecDiff;
+use crate::event::IEventObserver;
+use crate::observable::Change;
+use crate::observable::Observable;
+
+/// A change event that can be turned into the change undoing it.
+pub trait Reversible {
+    fn invert(&self) -> Self;
+}
+
+/// Something a change event can be applied to.
+pub trait Apply<E> {
+    fn apply(&mut self, change: &E);
+}
+
+struct Step<E> {
+    changes:   Vec<E>,
+    last_edit: Duration,
+}
+
+pub struct History<E, C: Clock = SystemClock> {
+    undo: RefCell<VecDeque<Step<E>>>,
+    redo: RefCell<Vec<Step<E>>>,
+    max_depth: usize,
+    window: Duration,
+    clock: C,
+    // Set while undo/redo replays changes, so the echoes are not recorded.
+    replaying: Cell<bool>,
+    // Cleared when the top undo step must not absorb further edits.
+    coalescible: Cell<bool>,
+}
+
+impl<E: Reversible + Clone> History<E, SystemClock> {
+    /// Keeps at most `max_depth` undo steps and does not coalesce edits.
+    pub fn new(max_depth: usize) -> History<E, SystemClock> {
+        History::with_clock(max_depth, SystemClock::new())
+    }
+}
+
+impl<E: Reversible + Clone, C: Clock> History<E, C> {
+    pub fn with_clock(max_depth: usize, clock: C) -> History<E, C> {
+        History {
+            undo: RefCell::new(VecDeque::new()),
+            redo: RefCell::new(Vec::new()),
+            max_depth,
+            window: Duration::ZERO,
+            clock,
+            replaying: Cell::new(false),
+            coalescible: Cell::new(false),
+        }
+    }
+
+    /// Merges edits arriving at most `window` after the previous edit into
+    /// the same undo step.
+    pub fn coalesce_within(mut self, window: Duration) -> History<E, C> {
+        self.window = window;
+        self
+    }
+
+    /// Starts a new undo step with the next edit, regardless of timing.
+    pub fn seal(&self) {
+        self.coalescible.set(false);
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.borrow().is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.borrow().is_empty()
+    }
+
+    /// Reverts the most recent step on `target`. Returns `false` if there
+    /// was nothing to undo.
+    pub fn undo(&self, target: &mut impl Apply<E>) -> bool {
+        let Some(step) = self.undo.borrow_mut().pop_back() else {
+            return false;
+        };
+        self.replay(target, step.changes.iter().rev().map(E::invert));
+        self.redo.borrow_mut().push(step);
+        self.seal();
+        true
+    }
+
+    /// Re-applies the most recently undone step on `target`. Returns `false`
+    /// if there was nothing to redo.
+    pub fn redo(&self, target: &mut impl Apply<E>) -> bool {
+        let Some(step) = self.redo.borrow_mut().pop() else {
+            return false;
+        };
+        self.replay(target, step.changes.iter().cloned());
+        self.undo.borrow_mut().push_back(step);
+        self.seal();
+        true
+    }
+
+    pub fn clear(&self) {
+        self.undo.borrow_mut().clear();
+        self.redo.borrow_mut().clear();
+    }
+
+    fn replay(
+        &self,
+        target: &mut impl Apply<E>,
+        changes: impl Iterator<Item = E>,
+    ) {
+        self.replaying.set(true);
+        let _reset = ReplayScope(&self.replaying);
+        for change in changes {
+            target.apply(&change);
+        }
+    }
+
+    fn record(&self, change: &E) {
+        let now = self.clock.now();
+        self.redo.borrow_mut().clear();
+        let mut undo = self.undo.borrow_mut();
+        if let Some(top) = undo.back_mut()
+            && self.coalescible.get()
+            && now.saturating_sub(top.last_edit) <= self.window
+        {
+            top.changes.push(change.clone());
+            top.last_edit = now;
+            return;
+        }
+        undo.push_back(Step {
+            changes:   vec![change.clone()],
+            last_edit: now,
+        });
+        if undo.len() > self.max_depth {
+            undo.pop_front();
+        }
+        self.coalescible.set(self.window > Duration::ZERO);
+    }
+}
+
+struct ReplayScope<'c>(&'c Cell<bool>);
+
+impl Drop for ReplayScope<'_> {
+    fn drop(&mut self) {
+        self.0.set(false);
+    }
+}
+
+impl<E: Reversible + Clone, C: Clock> IEventObserver<E> for History<E, C> {
+    fn update(&self, event: &E) {
+        if !self.replaying.get() {
+            self.record(event);
+        }
+    }
+}
+
+impl<E, C: Clock> PartialEq for History<E, C> {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl<T: Clone> Reversible for Change<T> {
+    fn invert(&self) -> Self {
+        Change {
+            old: self.new.clone(),
+            new: self.old.clone(),
+        }
+    }
+}
+
+impl<T: Clone> Reversible for VecDiff<T> {
+    fn invert(&self) -> Self {
+        match self.clone() {
+            VecDiff::Insert { index, value } => {
+                VecDiff::Remove { index, value }
+            },
+            VecDiff::Remove { index, value } => {
+                VecDiff::Insert { index, value }
+            },
+            VecDiff::Set { index, old, new } => {
+                VecDiff::Set {
+                    index,
+                    old: new,
+                    new: old,
+                }
+            },
+            VecDiff::Move { from, to } => {
+                VecDiff::Move {
+                    from: to,
+                    to:   from,
+                }
+            },
+            VecDiff::Clear { removed } => {
+                VecDiff::Replace {
+                    old: Vec::new(),
+                    new: removed,
+                }
+            },
+            VecDiff::Replace { old, new } => {
+                VecDiff::Replace { old: new, new: old }
+            },
+        }
+    }
+}
+
+impl<K: Clone + Eq + Hash, V: Clone> Reversible for MapDiff<K, V> {
+    fn invert(&self) -> Self {
+        match self.clone() {
+            MapDiff::Insert {
+                key,
+                value,
+                old: None,
+            } => MapDiff::Remove { key, value },
+            MapDiff::Insert {
+                key,
+                value,
+                old: Some(old),
+            } => {
+                MapDiff::Insert {
+                    key,
+                    value: old,
+                    old: Some(value),
+                }
+            },
+            MapDiff::Remove { key, value } => {
+                MapDiff::Insert {
+                    key,
+                    value,
+                    old: None,
+                }
+            },
+            MapDiff::Clear { removed } => {
+                MapDiff::Replace {
+                    old: HashMap::new(),
+                    new: removed,
+                }
+            },
+            MapDiff::Replace { old, new } => {
+                MapDiff::Replace { old: new, new: old }
+            },
+        }
+    }
+}
+
+impl<'a, T, O> Apply<Change<T>> for Observable<'a, T, O>
+where
+    T: Clone + PartialEq,
+    O: IEventObserver<Change<T>> + PartialEq,
+{
+    fn apply(&mut self, change: &Change<T>) {
+        self.set(change.new.clone());
+    }
+}
+
+impl<'a, T, O> Apply<VecDiff<T>> for ObservableVec<'a, T, O>
+where
+    T: Clone,
+    O: IEventObserver<VecDiff<T>> + PartialEq,
+{
+    fn apply(&mut self, change: &VecDiff<T>) {
+        match change {
+            VecDiff::Insert { index, value } => {
+                self.insert(*index, value.clone())
+            },
+            VecDiff::Remove { index, .. } => {
+                self.remove(*index);
+            },
+            VecDiff::Set { index, new, .. } => {
+                self.set(*index, new.clone());
+            },
+            VecDiff::Move { from, to } => self.move_item(*from, *to),
+            VecDiff::Clear { .. } => self.clear(),
+            VecDiff::Replace { new, .. } => self.replace(new.clone()),
+        }
+    }
+}
+
+impl<'a, K, V, O> Apply<MapDiff<K, V>> for ObservableMap<'a, K, V, O>
+where
+    K: Clone + Eq + Hash,
+    V: Clone,
+    O: IEventObserver<MapDiff<K, V>> + PartialEq,
+{
+    fn apply(&mut self, change: &MapDiff<K, V>) {
+        match change {
+            MapDiff::Insert { key, value, .. } => {
+                self.insert(key.clone(), value.clone());
+            },
+            MapDiff::Remove { key, .. } => {
+                self.remove(key);
+            },
+            MapDiff::Clear { .. } => self.clear(),
+            MapDiff::Replace { new, .. } => self.replace(new.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::event::IEventSubject;
+
+    // Forwards to a history if it has one and logs what it saw, so a test
+    // can watch the notifications undo and redo produce.
+    struct Tap<'h, E, C: Clock> {
+        history: Option<&'h History<E, C>>,
+        log:     RefCell<Vec<E>>,
+    }
+
+    impl<'h, E, C: Clock> Tap<'h, E, C> {
+        fn new(history: Option<&'h History<E, C>>) -> Self {
+            Tap {
+                history,
+                log: RefCell::new(Vec::new()),
+            }
+        }
+    }
+
+    impl<E, C: Clock> PartialEq for Tap<'_, E, C> {
+        fn eq(&self, other: &Self) -> bool {
+            std::ptr::eq(self, other)
+        }
+    }
+
+    impl<E: Reversible + Clone, C: Clock> IEventObserver<E> for Tap<'_, E, C> {
+        fn update(&self, event: &E) {
+            if let Some(history) = self.history {
+                history.update(event);
+            }
+            self.log.borrow_mut().push(event.clone());
+        }
+    }
+
+    #[test]
+    fn test_undo_redo_value() {
+        let history = History::new(10);
+        let recorder = Tap::new(Some(&history));
+        let watcher = Tap::new(None);
+        let mut cell = Observable::new(1);
+        cell.attach(&recorder);
+        cell.attach(&watcher);
+
+        cell.set(2);
+        cell.set(3);
+        assert!(history.undo(&mut cell));
+        assert_eq!(*cell.get(), 2);
+        assert!(history.undo(&mut cell));
+        assert_eq!(*cell.get(), 1);
+        assert!(!history.undo(&mut cell));
+        assert!(history.redo(&mut cell));
+        assert_eq!(*cell.get(), 2);
+
+        // Undo and redo were announced like any other change.
+        let seen: Vec<_> = watcher.log.borrow().iter().map(|c| c.new).collect();
+        assert_eq!(seen, vec![2, 3, 2, 1, 2]);
+        assert!(history.can_undo() && history.can_redo());
+    }
+
+    #[test]
+    fn test_new_edit_clears_redo() {
+        let history = History::new(10);
+        let recorder = Tap::new(Some(&history));
+        let mut cell = Observable::new(1);
+        cell.attach(&recorder);
+
+        cell.set(2);
+        history.undo(&mut cell);
+        cell.set(5);
+
+        assert!(!history.can_redo());
+        history.undo(&mut cell);
+        assert_eq!(*cell.get(), 1);
+    }
+
+    #[test]
+    fn test_depth_is_bounded() {
+        let history = History::new(3);
+        let recorder = Tap::new(Some(&history));
+        let mut cell = Observable::new(0);
+        cell.attach(&recorder);
+
+        for value in 1..=5 {
+            cell.set(value);
+        }
+        while history.undo(&mut cell) {}
+
+        assert_eq!(*cell.get(), 2);
+    }
+
+    #[test]
+    fn test_rapid_edits_coalesce() {
+        let clock = ManualClock::new();
+        let history = History::with_clock(10, &clock)
+            .coalesce_within(Duration::from_millis(100));
+        let recorder = Tap::new(Some(&history));
+        let mut cell = Observable::new(String::new());
+        cell.attach(&recorder);
+
+        for ch in ["h", "he", "hel"] {
+            cell.set(ch.to_string());
+            clock.advance(Duration::from_millis(50));
+        }
+        clock.advance(Duration::from_secs(1));
+        cell.set("hello".to_string());
+
+        history.undo(&mut cell);
+        assert_eq!(cell.get(), "hel");
+        history.undo(&mut cell);
+        assert_eq!(cell.get(), "");
+        assert!(!history.can_undo());
+    }
+
+    #[test]
+    fn test_seal_starts_new_step() {
+        let clock = ManualClock::new();
+        let history =
+            History::with_clock(10, &clock).coalesce_within(Duration::MAX);
+        let recorder = Tap::new(Some(&history));
+        let mut cell = Observable::new(0);
+        cell.attach(&recorder);
+
+        cell.set(1);
+        history.seal();
+        cell.set(2);
+
+        history.undo(&mut cell);
+        assert_eq!(*cell.get(), 1);
+    }
+
+    #[test]
+    fn test_undo_all_vec_edits() {
+        let history = History::new(100);
+        let recorder = Tap::new(Some(&history));
+        let mirror = Tap::new(None);
+        let mut list = ObservableVec::from_vec(vec![1, 2, 3]);
+        list.attach(&recorder);
+        list.attach(&mirror);
+
+        list.push(4);
+        list.remove(0);
+        list.set(0, 20);
+        list.move_item(0, 2);
+        list.clear();
+        list.replace(vec![7, 8]);
+        list.insert(1, 9);
+        let edited = list.as_slice().to_vec();
+
+        while history.undo(&mut list) {}
+        assert_eq!(list.as_slice(), &[1, 2, 3]);
+        while history.redo(&mut list) {}
+        assert_eq!(list.as_slice(), edited.as_slice());
+
+        // The mirror rebuilt the same state from the replayed diffs.
+        let mut copy = vec![1, 2, 3];
+        for diff in mirror.log.borrow().iter() {
+            diff.apply_to(&mut copy);
+        }
+        assert_eq!(copy, edited);
+    }
+
+    #[test]
+    fn test_undo_all_map_edits() {
+        let history = History::new(100);
+        let recorder = Tap::new(Some(&history));
+        let mut map = ObservableMap::from_map(HashMap::from([(1, 'a')]));
+        map.attach(&recorder);
+
+        map.insert(2, 'b');
+        map.insert(1, 'z');
+        map.remove(&2);
+        map.clear();
+        map.replace(HashMap::from([(5, 'e')]));
+
+        while history.undo(&mut map) {}
+        assert_eq!(*map.as_map(), HashMap::from([(1, 'a')]));
+        while history.redo(&mut map) {}
+        assert_eq!(*map.as_map(), HashMap::from([(5, 'e')]));
+    }
+}
@@ -0,0 +1,331 @@
+//! A subject that can describe itself at runtime.
+//!
+//! [`InspectableSubject`] keeps, for every attached observer, an
+//! [`ObserverId`], its type or the name it was attached under, an optional
+//! owner tag, the named filters installed in front of it, and how many events
+//! it was sent or spared.
+//! [`InspectableSubject::observers`] returns all of that as [`ObserverInfo`],
+//! and the `Debug` impl prints it, so `{:#?}` on a stuck subject shows who is
+//! listening and what they last heard.
+
+use std::cell::Cell;
+use std::fmt;
+use std::time::Duration;
+
+use crate::clock::Clock;
+use crate::clock::SystemClock;
+use crate::event::IEventObserver;
+use crate::event::ObserverId;
+
+/// A snapshot of what the subject knows about one observer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObserverInfo {
+    pub id: ObserverId,
+    /// Type of the observer, or the name it was attached under with
+    /// [`attach_named`](InspectableSubject::attach_named).
+    pub type_name: &'static str,
+    pub owner: Option<String>,
+    /// Names of the filters an event must pass, in the order they run.
+    pub filters: Vec<String>,
+    pub deliveries: u64,
+    /// Events a filter kept from the observer.
+    pub filtered: u64,
+    /// Clock reading at the last delivery.
+    pub last_delivery: Option<Duration>,
+}
+
+struct Filter<'a, E> {
+    name: String,
+    pass: Box<dyn Fn(&E) -> bool + 'a>,
+}
+
+struct Entry<'a, E, T> {
+    id: ObserverId,
+    observer: &'a T,
+    type_name: &'static str,
+    owner: Option<String>,
+    filters: Vec<Filter<'a, E>>,
+    deliveries: Cell<u64>,
+    filtered: Cell<u64>,
+    last_delivery: Cell<Option<Duration>>,
+}
+
+pub struct InspectableSubject<'a, E, T, C = SystemClock> {
+    observers: Vec<Entry<'a, E, T>>,
+    next_id:   u64,
+    published: Cell<u64>,
+    clock:     C,
+}
+
+impl<'a, E, T: IEventObserver<E> + PartialEq> InspectableSubject<'a, E, T> {
+    pub fn new() -> InspectableSubject<'a, E, T> {
+        InspectableSubject::with_clock(SystemClock::new())
+    }
+}
+
+impl<'a, E, T: IEventObserver<E> + PartialEq> Default
+    for InspectableSubject<'a, E, T>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, E, T, C> InspectableSubject<'a, E, T, C>
+where
+    T: IEventObserver<E> + PartialEq,
+    C: Clock,
+{
+    /// Stamps deliveries with `clock`.
+    pub fn with_clock(clock: C) -> InspectableSubject<'a, E, T, C> {
+        InspectableSubject {
+            observers: Vec::new(),
+            next_id: 0,
+            published: Cell::new(0),
+            clock,
+        }
+    }
+
+    /// Attaches `observer`, reported under the name of `T`.
+    pub fn attach(&mut self, observer: &'a T) -> ObserverId {
+        self.attach_named(observer, std::any::type_name::<T>())
+    }
+
+    /// Attaches `observer`, reported under `type_name`, to tell apart
+    /// observers when `T` covers several kinds of them, such as an enum.
+    pub fn attach_named(
+        &mut self,
+        observer: &'a T,
+        type_name: &'static str,
+    ) -> ObserverId {
+        let id = ObserverId(self.next_id);
+        self.next_id += 1;
+        self.observers.push(Entry {
+            id,
+            observer,
+            type_name,
+            owner: None,
+            filters: Vec::new(),
+            deliveries: Cell::new(0),
+            filtered: Cell::new(0),
+            last_delivery: Cell::new(None),
+        });
+        id
+    }
+
+    pub fn detach(&mut self, observer: &'a T) {
+        if let Some(idx) =
+            self.observers.iter().position(|x| x.observer == observer)
+        {
+            self.observers.remove(idx);
+        }
+    }
+
+    /// Records `owner` as responsible for observer `id`. Returns `false` if
+    /// no such observer is attached.
+    pub fn tag(&mut self, id: ObserverId, owner: &str) -> bool {
+        self.entry_mut(id)
+            .map(|entry| entry.owner = Some(owner.to_string()))
+            .is_some()
+    }
+
+    /// Installs a filter in front of observer `id`: it is only sent events
+    /// for which `pass` returns `true`. Returns `false` if no such observer
+    /// is attached.
+    pub fn filter(
+        &mut self,
+        id: ObserverId,
+        name: &str,
+        pass: impl Fn(&E) -> bool + 'a,
+    ) -> bool {
+        self.entry_mut(id)
+            .map(|entry| {
+                entry.filters.push(Filter {
+                    name: name.to_string(),
+                    pass: Box::new(pass),
+                })
+            })
+            .is_some()
+    }
+
+    pub fn notify_observers(&self, event: &E) {
+        self.published.set(self.published.get() + 1);
+        for entry in self.observers.iter() {
+            if !entry.filters.iter().all(|filter| (filter.pass)(event)) {
+                entry.filtered.set(entry.filtered.get() + 1);
+                continue;
+            }
+            entry.deliveries.set(entry.deliveries.get() + 1);
+            entry.last_delivery.set(Some(self.clock.now()));
+            entry.observer.update(event);
+        }
+    }
+
+    /// Describes observer `id`, or `None` if it is not attached.
+    pub fn observer(&self, id: ObserverId) -> Option<ObserverInfo> {
+        self.observers.iter().find(|x| x.id == id).map(Entry::info)
+    }
+
+    /// Describes every attached observer, in attachment order.
+    pub fn observers(&self) -> Vec<ObserverInfo> {
+        self.observers.iter().map(Entry::info).collect()
+    }
+
+    /// Events passed to [`notify_observers`](Self::notify_observers) so far.
+    pub fn published(&self) -> u64 {
+        self.published.get()
+    }
+
+    pub fn len(&self) -> usize {
+        self.observers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.observers.is_empty()
+    }
+
+    fn entry_mut(&mut self, id: ObserverId) -> Option<&mut Entry<'a, E, T>> {
+        self.observers.iter_mut().find(|x| x.id == id)
+    }
+}
+
+impl<E, T> Entry<'_, E, T> {
+    fn info(&self) -> ObserverInfo {
+        ObserverInfo {
+            id: self.id,
+            type_name: self.type_name,
+            owner: self.owner.clone(),
+            filters: self.filters.iter().map(|x| x.name.clone()).collect(),
+            deliveries: self.deliveries.get(),
+            filtered: self.filtered.get(),
+            last_delivery: self.last_delivery.get(),
+        }
+    }
+}
+
+impl<E, T, C> fmt::Debug for InspectableSubject<'_, E, T, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let observers: Vec<_> =
+            self.observers.iter().map(Entry::info).collect();
+        f.debug_struct("InspectableSubject")
+            .field("published", &self.published.get())
+            .field("observers", &observers)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::clock::ManualClock;
+
+    #[derive(Default)]
+    struct Recorder {
+        seen: RefCell<Vec<i32>>,
+    }
+
+    impl PartialEq for Recorder {
+        fn eq(&self, other: &Self) -> bool {
+            std::ptr::eq(self, other)
+        }
+    }
+
+    impl IEventObserver<i32> for Recorder {
+        fn update(&self, event: &i32) {
+            self.seen.borrow_mut().push(*event);
+        }
+    }
+
+    #[test]
+    fn test_reports_counts_tags_and_filters() {
+        let clock = ManualClock::new();
+        let all = Recorder::default();
+        let positive = Recorder::default();
+        let mut subject = InspectableSubject::with_clock(&clock);
+        let all_id = subject.attach(&all);
+        let positive_id = subject.attach(&positive);
+        assert!(subject.tag(positive_id, "billing"));
+        assert!(subject.filter(positive_id, "positive", |n: &i32| *n > 0));
+
+        for n in [3, -1, 4] {
+            clock.advance(Duration::from_secs(1));
+            subject.notify_observers(&n);
+        }
+        clock.advance(Duration::from_secs(1));
+        subject.notify_observers(&-5);
+
+        assert_eq!(*positive.seen.borrow(), vec![3, 4]);
+        assert_eq!(subject.published(), 4);
+        let info = subject.observer(positive_id).unwrap();
+        assert_eq!(info.owner.as_deref(), Some("billing"));
+        assert_eq!(info.filters, vec!["positive"]);
+        assert_eq!((info.deliveries, info.filtered), (2, 2));
+        assert_eq!(info.last_delivery, Some(Duration::from_secs(3)));
+        assert!(info.type_name.ends_with("Recorder"));
+        let info = subject.observer(all_id).unwrap();
+        assert_eq!((info.deliveries, info.filtered), (4, 0));
+        assert_eq!(info.owner, None);
+    }
+
+    #[test]
+    fn test_names_given_at_attach() {
+        #[derive(PartialEq)]
+        enum Sink {
+            Audit,
+            Mailer,
+        }
+
+        impl IEventObserver<i32> for Sink {
+            fn update(&self, _event: &i32) {}
+        }
+
+        let (audit, mailer, plain) = (Sink::Audit, Sink::Mailer, Sink::Audit);
+        let mut subject = InspectableSubject::new();
+        subject.attach_named(&audit, "Audit");
+        subject.attach_named(&mailer, "Mailer");
+        subject.attach(&plain);
+
+        let names: Vec<_> =
+            subject.observers().iter().map(|x| x.type_name).collect();
+        assert_eq!(names[..2], ["Audit", "Mailer"]);
+        assert!(names[2].ends_with("Sink"));
+    }
+
+    #[test]
+    fn test_ids_are_not_reused() {
+        let first = Recorder::default();
+        let second = Recorder::default();
+        let mut subject = InspectableSubject::new();
+        let first_id = subject.attach(&first);
+        subject.detach(&first);
+        let second_id = subject.attach(&second);
+
+        assert_ne!(first_id, second_id);
+        assert_eq!(subject.observer(first_id), None);
+        assert!(!subject.tag(first_id, "gone"));
+        let ids: Vec<_> = subject.observers().iter().map(|x| x.id).collect();
+        assert_eq!(ids, vec![second_id]);
+        assert_eq!(second_id.to_string(), "#1");
+    }
+
+    #[test]
+    fn test_debug_output() {
+        let clock = ManualClock::new();
+        let observer = Recorder::default();
+        let mut subject = InspectableSubject::with_clock(&clock);
+        let id = subject.attach(&observer);
+        subject.tag(id, "audit");
+        subject.filter(id, "even", |n: &i32| n % 2 == 0);
+        subject.notify_observers(&2);
+
+        let debug = format!("{subject:?}");
+
+        assert!(debug.starts_with("InspectableSubject { published: 1, "));
+        assert!(debug.contains("owner: Some(\"audit\")"));
+        assert!(debug.contains("filters: [\"even\"]"));
+        assert!(debug.contains("deliveries: 1"));
+        assert!(debug.contains("last_delivery: Some(0ns)"));
+    }
+}
@@ -0,0 +1,393 @@
+//! Cross-cutting hooks around every notification.
+//!
+//! An [`InterceptedSubject`] runs a chain of [`Interceptor`]s on each call to
+//! [`notify_observers`](InterceptedSubject::notify_observers):
+//!
+//! 1. Each interceptor's [`before`](Interceptor::before) sees the event in turn
+//!    and may change it, for instance to redact a field, or drop it, for
+//!    instance when the caller is not allowed to publish it. A dropped event
+//!    reaches neither the remaining interceptors nor any observer.
+//! 2. The event, as changed, is delivered to every observer, and the result and
+//!    duration of each delivery is recorded as an [`Outcome`].
+//! 3. Each interceptor's [`after`](Interceptor::after) sees the delivered event
+//!    and all outcomes, in reverse order, so the first interceptor wraps all
+//!    the others.
+//!
+//! Interceptors run in ascending order of the key given to
+//! [`intercept`](InterceptedSubject::intercept); equal keys keep the order
+//! they were added in.
+
+use std::time::Duration;
+
+use crate::clock::Clock;
+use crate::clock::SystemClock;
+use crate::event::IFallibleObserver;
+use crate::event::ObserverError;
+use crate::event::ObserverId;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    Continue,
+    Drop,
+}
+
+pub trait Interceptor<E> {
+    fn before(&self, _event: &mut E) -> Flow {
+        Flow::Continue
+    }
+
+    fn after(&self, _event: &E, _outcomes: &[Outcome]) {}
+}
+
+/// How delivering an event to one observer went.
+#[derive(Debug)]
+pub struct Outcome {
+    /// What [`attach`](InterceptedSubject::attach) returned for the observer.
+    pub observer: ObserverId,
+    pub elapsed:  Duration,
+    pub result:   Result<(), ObserverError>,
+}
+
+/// What became of one call to
+/// [`notify_observers`](InterceptedSubject::notify_observers).
+#[derive(Debug)]
+pub enum Delivery {
+    /// Dropped by the interceptor at this position in the chain.
+    Dropped {
+        by: usize,
+    },
+    Delivered(Vec<Outcome>),
+}
+
+pub struct InterceptedSubject<'a, E, T, C = SystemClock> {
+    observers: Vec<(ObserverId, &'a T)>,
+    next_id: u64,
+    interceptors: Vec<(i32, &'a dyn Interceptor<E>)>,
+    clock: C,
+}
+
+impl<'a, E: Clone, T: IFallibleObserver<E> + PartialEq>
+    InterceptedSubject<'a, E, T>
+{
+    pub fn new() -> InterceptedSubject<'a, E, T> {
+        InterceptedSubject::with_clock(SystemClock::new())
+    }
+}
+
+impl<'a, E: Clone, T: IFallibleObserver<E> + PartialEq> Default
+    for InterceptedSubject<'a, E, T>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, E, T, C> InterceptedSubject<'a, E, T, C>
+where
+    E: Clone,
+    T: IFallibleObserver<E> + PartialEq,
+    C: Clock,
+{
+    /// Times deliveries with `clock`.
+    pub fn with_clock(clock: C) -> InterceptedSubject<'a, E, T, C> {
+        InterceptedSubject {
+            observers: Vec::new(),
+            next_id: 0,
+            interceptors: Vec::new(),
+            clock,
+        }
+    }
+
+    /// Returns the ID that [`Outcome`]s for `observer` carry; it is never
+    /// reused by this subject.
+    pub fn attach(&mut self, observer: &'a T) -> ObserverId {
+        let id = ObserverId(self.next_id);
+        self.next_id += 1;
+        self.observers.push((id, observer));
+        id
+    }
+
+    pub fn detach(&mut self, observer: &'a T) {
+        if let Some(idx) =
+            self.observers.iter().position(|(_, x)| *x == observer)
+        {
+            self.observers.remove(idx);
+        }
+    }
+
+    /// Adds `interceptor` to the chain at position `order`; lower runs
+    /// first.
+    pub fn intercept(
+        &mut self,
+        order: i32,
+        interceptor: &'a dyn Interceptor<E>,
+    ) {
+        let idx = self.interceptors.partition_point(|(o, _)| *o <= order);
+        self.interceptors.insert(idx, (order, interceptor));
+    }
+
+    /// Removes `interceptor` from the chain.
+    pub fn remove_interceptor(&mut self, interceptor: &'a dyn Interceptor<E>) {
+        self.interceptors
+            .retain(|(_, x)| !std::ptr::addr_eq(*x, interceptor));
+    }
+
+    /// Runs the chain and delivers the event unless an interceptor drops it.
+    pub fn notify_observers(&self, event: &E) -> Delivery {
+        let mut event = event.clone();
+        for (idx, (_, interceptor)) in self.interceptors.iter().enumerate() {
+            if interceptor.before(&mut event) == Flow::Drop {
+                return Delivery::Dropped { by: idx };
+            }
+        }
+        let outcomes: Vec<_> = self
+            .observers
+            .iter()
+            .map(|&(observer, item)| {
+                let start = self.clock.now();
+                let result = item.try_update(&event);
+                Outcome {
+                    observer,
+                    elapsed: self.clock.now().saturating_sub(start),
+                    result,
+                }
+            })
+            .collect();
+        for (_, interceptor) in self.interceptors.iter().rev() {
+            interceptor.after(&event, &outcomes);
+        }
+        Delivery::Delivered(outcomes)
+    }
+
+    pub fn len(&self) -> usize {
+        self.observers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.observers.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::event::IEventObserver;
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Message {
+        author: &'static str,
+        body:   String,
+    }
+
+    fn message(author: &'static str, body: &str) -> Message {
+        Message {
+            author,
+            body: body.to_string(),
+        }
+    }
+
+    struct Inbox<'c> {
+        clock:    &'c ManualClock,
+        cost:     Duration,
+        received: RefCell<Vec<Message>>,
+    }
+
+    impl<'c> Inbox<'c> {
+        fn new(clock: &'c ManualClock, cost: Duration) -> Inbox<'c> {
+            Inbox {
+                clock,
+                cost,
+                received: RefCell::new(Vec::new()),
+            }
+        }
+    }
+
+    impl PartialEq for Inbox<'_> {
+        fn eq(&self, other: &Self) -> bool {
+            std::ptr::eq(self, other)
+        }
+    }
+
+    impl IEventObserver<Message> for Inbox<'_> {
+        fn update(&self, event: &Message) {
+            self.clock.advance(self.cost);
+            self.received.borrow_mut().push(event.clone());
+        }
+    }
+
+    // Appends "<name> before" and "<name> after" to a shared log.
+    struct Logger<'l> {
+        name: &'static str,
+        log:  &'l RefCell<Vec<String>>,
+    }
+
+    impl Interceptor<Message> for Logger<'_> {
+        fn before(&self, _event: &mut Message) -> Flow {
+            self.log.borrow_mut().push(format!("{} before", self.name));
+            Flow::Continue
+        }
+
+        fn after(&self, _event: &Message, _outcomes: &[Outcome]) {
+            self.log.borrow_mut().push(format!("{} after", self.name));
+        }
+    }
+
+    struct Redact;
+
+    impl Interceptor<Message> for Redact {
+        fn before(&self, event: &mut Message) -> Flow {
+            event.body = event.body.replace("hunter2", "*******");
+            Flow::Continue
+        }
+    }
+
+    struct Authorize {
+        allowed: &'static str,
+        denied:  Cell<u32>,
+    }
+
+    impl Interceptor<Message> for Authorize {
+        fn before(&self, event: &mut Message) -> Flow {
+            if event.author == self.allowed {
+                Flow::Continue
+            } else {
+                self.denied.set(self.denied.get() + 1);
+                Flow::Drop
+            }
+        }
+    }
+
+    #[derive(Default)]
+    struct Timing {
+        slowest: Cell<Duration>,
+    }
+
+    impl Interceptor<Message> for Timing {
+        fn after(&self, _event: &Message, outcomes: &[Outcome]) {
+            for outcome in outcomes {
+                self.slowest.set(self.slowest.get().max(outcome.elapsed));
+            }
+        }
+    }
+
+    #[test]
+    fn test_order_and_nesting() {
+        let clock = ManualClock::new();
+        let log = RefCell::new(Vec::new());
+        let inbox = Inbox::new(&clock, Duration::ZERO);
+        let outer = Logger {
+            name: "outer",
+            log:  &log,
+        };
+        let inner = Logger {
+            name: "inner",
+            log:  &log,
+        };
+        let mut subject = InterceptedSubject::with_clock(&clock);
+        subject.attach(&inbox);
+        // Added out of order on purpose.
+        subject.intercept(10, &inner);
+        subject.intercept(-5, &outer);
+
+        subject.notify_observers(&message("ann", "hi"));
+
+        assert_eq!(
+            *log.borrow(),
+            vec!["outer before", "inner before", "inner after", "outer after"]
+        );
+
+        log.borrow_mut().clear();
+        subject.remove_interceptor(&outer);
+        subject.notify_observers(&message("ann", "hi"));
+        assert_eq!(*log.borrow(), vec!["inner before", "inner after"]);
+    }
+
+    #[test]
+    fn test_redaction_and_authorization() {
+        let clock = ManualClock::new();
+        let inbox = Inbox::new(&clock, Duration::ZERO);
+        let redact = Redact;
+        let authorize = Authorize {
+            allowed: "ann",
+            denied:  Cell::new(0),
+        };
+        let mut subject = InterceptedSubject::with_clock(&clock);
+        subject.attach(&inbox);
+        subject.intercept(0, &authorize);
+        subject.intercept(1, &redact);
+
+        let sent = subject.notify_observers(&message("ann", "pw is hunter2"));
+        let blocked = subject.notify_observers(&message("eve", "hello"));
+
+        assert!(matches!(sent, Delivery::Delivered(ref o) if o.len() == 1));
+        assert!(matches!(blocked, Delivery::Dropped { by: 0 }));
+        assert_eq!(
+            *inbox.received.borrow(),
+            vec![message("ann", "pw is *******")]
+        );
+        assert_eq!(authorize.denied.get(), 1);
+    }
+
+    #[test]
+    fn test_per_observer_outcomes() {
+        struct Failing;
+
+        impl PartialEq for Failing {
+            fn eq(&self, other: &Self) -> bool {
+                std::ptr::eq(self, other)
+            }
+        }
+
+        impl IFallibleObserver<Message> for Failing {
+            fn try_update(
+                &self,
+                _event: &Message,
+            ) -> Result<(), ObserverError> {
+                Err("mailbox full".into())
+            }
+        }
+
+        let clock = ManualClock::new();
+        let fast = Inbox::new(&clock, Duration::from_millis(1));
+        let slow = Inbox::new(&clock, Duration::from_millis(40));
+        let timing = Timing::default();
+        let mut subject = InterceptedSubject::with_clock(&clock);
+        subject.attach(&fast);
+        let slow_id = subject.attach(&slow);
+        subject.intercept(0, &timing);
+
+        let Delivery::Delivered(outcomes) =
+            subject.notify_observers(&message("ann", "hi"))
+        else {
+            panic!("event was dropped");
+        };
+        assert_eq!(outcomes[0].elapsed, Duration::from_millis(1));
+        assert_eq!(outcomes[1].observer, slow_id);
+        assert_eq!(timing.slowest.get(), Duration::from_millis(40));
+
+        // The ID stays with the observer when others leave.
+        subject.detach(&fast);
+        let Delivery::Delivered(outcomes) =
+            subject.notify_observers(&message("ann", "hi"))
+        else {
+            panic!("event was dropped");
+        };
+        assert_eq!(outcomes[0].observer, slow_id);
+
+        let failing = Failing;
+        let mut subject: InterceptedSubject<Message, Failing> =
+            InterceptedSubject::new();
+        subject.attach(&failing);
+        let Delivery::Delivered(outcomes) =
+            subject.notify_observers(&message("ann", "hi"))
+        else {
+            panic!("event was dropped");
+        };
+        let error = outcomes[0].result.as_ref().unwrap_err();
+        assert_eq!(error.to_string(), "mailbox full");
+    }
+}
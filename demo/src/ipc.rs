@@ -0,0 +1,529 @@
+//! Observers in other processes, reached over a Unix domain socket.
+//!
+//! A [`SocketBridge`] is an ordinary [`IEventObserver`]: attach it to any
+//! subject and every event is encoded and written to each connected client.
+//! On the other side a [`SocketClient`] reads those frames back and turns them
+//! into `update` calls on local observers.
+//!
+//! # Wire format
+//!
+//! Every message is one frame:
+//!
+//! | bytes | field                              |
+//! |-------|------------------------------------|
+//! | 2     | magic, `b"OB"`                     |
+//! | 1     | wire version, [`WIRE_VERSION`]     |
+//! | 1     | kind: `0` event, `1` close         |
+//! | 4     | payload length, big endian         |
+//! | n     | payload, encoded by [`WireEvent`]  |
+//!
+//! Frames with a different magic or version are rejected with
+//! [`io::ErrorKind::InvalidData`] rather than guessed at.
+//!
+//! # Reconnects
+//!
+//! Dropping a bridge sends a close frame, which ends [`SocketClient::run`].
+//! A connection that ends without one, because the publishing process died
+//! or restarted, is reconnected according to the client's retry policy.
+
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::marker::PhantomData;
+use std::os::unix::net::UnixListener;
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::codec::MAX_FRAME_LEN;
+use crate::event::IEventObserver;
+
+pub const WIRE_VERSION: u8 = 1;
+
+const MAGIC: [u8; 2] = *b"OB";
+const KIND_EVENT: u8 = 0;
+const KIND_CLOSE: u8 = 1;
+/// How often the acceptor looks for new clients and for shutdown.
+const ACCEPT_POLL: Duration = Duration::from_millis(10);
+
+/// Events that can cross a process boundary.
+pub trait WireEvent: Sized {
+    fn encode(&self, out: &mut Vec<u8>);
+    fn decode(bytes: &[u8]) -> io::Result<Self>;
+}
+
+impl WireEvent for () {
+    fn encode(&self, _out: &mut Vec<u8>) {}
+
+    fn decode(bytes: &[u8]) -> io::Result<Self> {
+        if bytes.is_empty() {
+            Ok(())
+        } else {
+            Err(invalid("unexpected payload for unit event"))
+        }
+    }
+}
+
+impl WireEvent for Vec<u8> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self);
+    }
+
+    fn decode(bytes: &[u8]) -> io::Result<Self> {
+        Ok(bytes.to_vec())
+    }
+}
+
+impl WireEvent for String {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self.as_bytes());
+    }
+
+    fn decode(bytes: &[u8]) -> io::Result<Self> {
+        String::from_utf8(bytes.to_vec()).map_err(|_| invalid("invalid UTF-8"))
+    }
+}
+
+impl WireEvent for u64 {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_be_bytes());
+    }
+
+    fn decode(bytes: &[u8]) -> io::Result<Self> {
+        let bytes =
+            bytes.try_into().map_err(|_| invalid("expected 8 bytes"))?;
+        Ok(u64::from_be_bytes(bytes))
+    }
+}
+
+enum Frame {
+    Event(Vec<u8>),
+    Close,
+}
+
+fn write_frame(
+    out: &mut impl Write,
+    kind: u8,
+    payload: &[u8],
+) -> io::Result<()> {
+    if payload.len() > MAX_FRAME_LEN {
+        return Err(invalid("frame too large"));
+    }
+    let mut frame = Vec::with_capacity(8 + payload.len());
+    frame.extend_from_slice(&MAGIC);
+    frame.push(WIRE_VERSION);
+    frame.push(kind);
+    frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    frame.extend_from_slice(payload);
+    out.write_all(&frame)
+}
+
+fn read_frame(input: &mut impl Read) -> io::Result<Frame> {
+    let mut header = [0; 8];
+    input.read_exact(&mut header)?;
+    if header[..2] != MAGIC {
+        return Err(invalid("bad frame magic"));
+    }
+    if header[2] != WIRE_VERSION {
+        return Err(invalid(&format!(
+            "unsupported wire version {}",
+            header[2]
+        )));
+    }
+    let len = u32::from_be_bytes([header[4], header[5], header[6], header[7]])
+        as usize;
+    if len > MAX_FRAME_LEN {
+        return Err(invalid("frame too large"));
+    }
+    let mut payload = vec![0; len];
+    input.read_exact(&mut payload)?;
+    match header[3] {
+        KIND_EVENT => Ok(Frame::Event(payload)),
+        KIND_CLOSE => Ok(Frame::Close),
+        kind => Err(invalid(&format!("unknown frame kind {kind}"))),
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+struct Clients {
+    streams: Mutex<Vec<UnixStream>>,
+    joined:  Condvar,
+}
+
+/// Publishes events to every client connected to a Unix socket.
+///
+/// Clients may connect at any time and only see events sent after they
+/// connected. A client whose connection fails is dropped.
+pub struct SocketBridge<E> {
+    path:     PathBuf,
+    clients:  Arc<Clients>,
+    shutdown: Arc<AtomicBool>,
+    acceptor: Option<JoinHandle<()>>,
+    _event:   PhantomData<fn(&E)>,
+}
+
+impl<E: WireEvent> SocketBridge<E> {
+    /// Listens on `path`, replacing a socket file left behind by an earlier
+    /// process.
+    pub fn bind(path: impl AsRef<Path>) -> io::Result<SocketBridge<E>> {
+        let path = path.as_ref().to_path_buf();
+        match std::fs::remove_file(&path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                return Err(err);
+            },
+            _ => {},
+        }
+        let listener = UnixListener::bind(&path)?;
+        // Polled, so shutdown never depends on a connection waking it up.
+        listener.set_nonblocking(true)?;
+        let clients = Arc::new(Clients {
+            streams: Mutex::new(Vec::new()),
+            joined:  Condvar::new(),
+        });
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let acceptor = {
+            let clients = Arc::clone(&clients);
+            let shutdown = Arc::clone(&shutdown);
+            thread::spawn(move || {
+                loop {
+                    // Read before accepting, so clients that connected before
+                    // shutdown are still taken in and sent a close frame.
+                    let stopping = shutdown.load(Ordering::SeqCst);
+                    match listener.accept() {
+                        Ok((stream, _)) => {
+                            let _ = stream.set_nonblocking(false);
+                            // A stalled client must not hold up the publisher.
+                            let _ = stream.set_write_timeout(Some(
+                                Duration::from_secs(5),
+                            ));
+                            clients.streams.lock().unwrap().push(stream);
+                            clients.joined.notify_all();
+                        },
+                        Err(_) if stopping => break,
+                        Err(_) => thread::sleep(ACCEPT_POLL),
+                    }
+                }
+            })
+        };
+        Ok(SocketBridge {
+            path,
+            clients,
+            shutdown,
+            acceptor: Some(acceptor),
+            _event: PhantomData,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn client_count(&self) -> usize {
+        self.clients.streams.lock().unwrap().len()
+    }
+
+    /// Blocks until at least `count` clients are connected. Returns `false`
+    /// if that did not happen within `timeout`.
+    pub fn wait_for_clients(&self, count: usize, timeout: Duration) -> bool {
+        let streams = self.clients.streams.lock().unwrap();
+        let (streams, _) = self
+            .clients
+            .joined
+            .wait_timeout_while(streams, timeout, |s| s.len() < count)
+            .unwrap();
+        streams.len() >= count
+    }
+
+    fn broadcast(&self, kind: u8, payload: &[u8]) {
+        self.clients
+            .streams
+            .lock()
+            .unwrap()
+            .retain_mut(|stream| write_frame(stream, kind, payload).is_ok());
+    }
+}
+
+impl<E: WireEvent> IEventObserver<E> for SocketBridge<E> {
+    fn update(&self, event: &E) {
+        let mut payload = Vec::new();
+        event.encode(&mut payload);
+        self.broadcast(KIND_EVENT, &payload);
+    }
+}
+
+impl<E> PartialEq for SocketBridge<E> {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl<E> Drop for SocketBridge<E> {
+    fn drop(&mut self) {
+        // Stop accepting first, so no client joins after the close frames.
+        self.shutdown.store(true, Ordering::SeqCst);
+        if let Some(acceptor) = self.acceptor.take() {
+            let _ = acceptor.join();
+        }
+        for stream in self.clients.streams.lock().unwrap().iter_mut() {
+            let _ = write_frame(stream, KIND_CLOSE, &[]);
+        }
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Receives events from a [`SocketBridge`], reconnecting when the connection
+/// is lost.
+pub struct SocketClient<E> {
+    path: PathBuf,
+    stream: Option<UnixStream>,
+    attempts: u32,
+    delay: Duration,
+    connected: bool,
+    reconnects: u64,
+    _event: PhantomData<fn() -> E>,
+}
+
+impl<E: WireEvent> SocketClient<E> {
+    /// Creates a client for the bridge at `path`. The connection is made on
+    /// the first [`recv`](Self::recv).
+    pub fn new(path: impl Into<PathBuf>) -> SocketClient<E> {
+        SocketClient {
+            path: path.into(),
+            stream: None,
+            attempts: 50,
+            delay: Duration::from_millis(100),
+            connected: false,
+            reconnects: 0,
+            _event: PhantomData,
+        }
+    }
+
+    /// Tries to connect up to `attempts` times, `delay` apart, before giving
+    /// up. Applies to the first connection and to every reconnect.
+    pub fn retry(mut self, attempts: u32, delay: Duration) -> SocketClient<E> {
+        self.attempts = attempts.max(1);
+        self.delay = delay;
+        self
+    }
+
+    /// Number of times the connection was re-established after being lost.
+    pub fn reconnects(&self) -> u64 {
+        self.reconnects
+    }
+
+    /// Waits for the next event. Returns `Ok(None)` once the bridge has shut
+    /// down cleanly.
+    pub fn recv(&mut self) -> io::Result<Option<E>> {
+        loop {
+            let mut stream = match self.stream.take() {
+                Some(stream) => stream,
+                None => self.connect()?,
+            };
+            match read_frame(&mut stream) {
+                Ok(Frame::Event(payload)) => {
+                    self.stream = Some(stream);
+                    return E::decode(&payload).map(Some);
+                },
+                Ok(Frame::Close) => return Ok(None),
+                // Lost without a close frame: reconnect on the next turn.
+                Err(err) if is_disconnect(&err) => {},
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Delivers events to `observer` until the bridge shuts down cleanly or
+    /// can no longer be reached.
+    pub fn run(&mut self, observer: &impl IEventObserver<E>) -> io::Result<()> {
+        while let Some(event) = self.recv()? {
+            observer.update(&event);
+        }
+        Ok(())
+    }
+
+    fn connect(&mut self) -> io::Result<UnixStream> {
+        let deadline = Instant::now() + self.delay * self.attempts;
+        let mut attempt = 1;
+        loop {
+            match UnixStream::connect(&self.path) {
+                Ok(stream) => {
+                    if self.connected {
+                        self.reconnects += 1;
+                    }
+                    self.connected = true;
+                    return Ok(stream);
+                },
+                Err(err)
+                    if attempt >= self.attempts
+                        || Instant::now() > deadline =>
+                {
+                    return Err(err);
+                },
+                Err(_) => {
+                    attempt += 1;
+                    thread::sleep(self.delay);
+                },
+            }
+        }
+    }
+}
+
+fn is_disconnect(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::UnexpectedEof
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::BrokenPipe
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+
+    fn socket_path(name: &str) -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("demo-ipc-{}-{name}.sock", std::process::id()))
+    }
+
+    #[derive(Default)]
+    struct Recorder {
+        seen: RefCell<Vec<String>>,
+    }
+
+    impl IEventObserver<String> for Recorder {
+        fn update(&self, event: &String) {
+            self.seen.borrow_mut().push(event.clone());
+        }
+    }
+
+    #[test]
+    fn test_frame_round_trip() {
+        let mut buf = Vec::new();
+        write_frame(&mut buf, KIND_EVENT, b"hello").unwrap();
+        write_frame(&mut buf, KIND_CLOSE, &[]).unwrap();
+
+        let mut input = &buf[..];
+        match read_frame(&mut input).unwrap() {
+            Frame::Event(payload) => assert_eq!(payload, b"hello"),
+            Frame::Close => panic!("expected an event"),
+        }
+        assert!(matches!(read_frame(&mut input).unwrap(), Frame::Close));
+        let eof = read_frame(&mut input).err().unwrap();
+        assert_eq!(eof.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_rejects_other_versions_and_magic() {
+        let mut buf = Vec::new();
+        write_frame(&mut buf, KIND_EVENT, b"x").unwrap();
+
+        let mut future = buf.clone();
+        future[2] = WIRE_VERSION + 1;
+        let err = read_frame(&mut &future[..]).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let mut garbage = buf.clone();
+        garbage[0] = b'X';
+        let err = read_frame(&mut &garbage[..]).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_bridge_to_client() {
+        let path = socket_path("bridge");
+        let bridge = SocketBridge::<String>::bind(&path).unwrap();
+        let reader = {
+            let path = path.clone();
+            thread::spawn(move || {
+                let recorder = Recorder::default();
+                SocketClient::new(path).run(&recorder).unwrap();
+                recorder.seen.into_inner()
+            })
+        };
+        assert!(bridge.wait_for_clients(1, Duration::from_secs(5)));
+
+        bridge.update(&"a".to_string());
+        bridge.update(&"b".to_string());
+        drop(bridge);
+
+        assert_eq!(reader.join().unwrap(), vec!["a", "b"]);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_late_client_gets_close_frame() {
+        let path = socket_path("late");
+        let bridge = SocketBridge::<String>::bind(&path).unwrap();
+        // Connected, but possibly not yet accepted, when the bridge drops.
+        let mut stream = UnixStream::connect(&path).unwrap();
+
+        drop(bridge);
+
+        assert!(matches!(read_frame(&mut stream).unwrap(), Frame::Close));
+    }
+
+    #[test]
+    fn test_drop_after_socket_file_removed() {
+        let path = socket_path("unlinked");
+        let bridge = SocketBridge::<String>::bind(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // Nothing can connect to the bridge now; dropping must still return.
+        let (done, dropped) = std::sync::mpsc::channel();
+        thread::spawn(move || {
+            drop(bridge);
+            let _ = done.send(());
+        });
+
+        assert!(dropped.recv_timeout(Duration::from_secs(5)).is_ok());
+    }
+
+    #[test]
+    fn test_client_reconnects_after_connection_loss() {
+        let path = socket_path("reconnect");
+        let listener = UnixListener::bind(&path).unwrap();
+        let server = thread::spawn(move || {
+            // Two connections; the first one drops without a close frame.
+            let (mut first, _) = listener.accept().unwrap();
+            write_frame(&mut first, KIND_EVENT, b"before").unwrap();
+            drop(first);
+            let (mut second, _) = listener.accept().unwrap();
+            write_frame(&mut second, KIND_EVENT, b"after").unwrap();
+            write_frame(&mut second, KIND_CLOSE, &[]).unwrap();
+        });
+        let mut client = SocketClient::<String>::new(&path)
+            .retry(20, Duration::from_millis(10));
+        let recorder = Recorder::default();
+
+        client.run(&recorder).unwrap();
+        server.join().unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(*recorder.seen.borrow(), vec!["before", "after"]);
+        assert_eq!(client.reconnects(), 1);
+    }
+
+    #[test]
+    fn test_gives_up_when_nobody_listens() {
+        let mut client = SocketClient::<u64>::new(socket_path("missing"))
+            .retry(3, Duration::from_millis(1));
+
+        assert!(client.recv().is_err());
+    }
+}
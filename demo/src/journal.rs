@@ -0,0 +1,808 @@
+//! Durable, append-only log of events.
+//!
+//! A [`Journal`] is an observer that appends every event it sees to a
+//! directory of segment files, each event encoded as one checksummed frame by
+//! an [`EventCodec`]. After a restart, [`Journal::replay`] feeds the events
+//! back in order so a subject and its observers can be rebuilt. Attach the
+//! journal only after replaying, or the replayed events are logged again.
+//!
+//! # Layout
+//!
+//! Segments are named after the sequence number of their first event,
+//! `00000000000000000042.log`, and a new one is started once the current
+//! segment reaches [`JournalOptions::segment_bytes`].
+//!
+//! [`Journal::compact`] bounds the log: it writes a single event that stands
+//! for everything so far (for instance a [`VecDiff::Replace`] carrying the
+//! whole collection) to a `.snapshot` file and then deletes the segments it
+//! covers. Replay starts from the newest snapshot.
+//!
+//! # Crash recovery
+//!
+//! A crash can leave the last segment ending in a partly written frame. On
+//! open, that segment is cut back to its last intact frame, but only if no
+//! intact frame follows the damaged one: damage anywhere else, including the
+//! middle of the last segment, means the log itself is corrupt and is
+//! reported as an error rather than cut away with the events after it.
+//!
+//! An append whose write fails is cut back out of the segment, so a later
+//! append does not land after the torn bytes. If even that fails, the journal
+//! refuses further appends until it is reopened. So it does if flushing an
+//! append fails: the event stays in the segment and keeps its sequence
+//! number, but what reached the disk is unknown.
+//!
+//! [`VecDiff::Replace`]: crate::collections::VecDiff::Replace
+
+use std::cell::RefCell;
+use std::fs;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::Write;
+use std::marker::PhantomData;
+use std::ops::ControlFlow;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::codec::BinaryCodec;
+use crate::codec::CodecError;
+use crate::codec::EventCodec;
+use crate::event::IEventObserver;
+
+/// When appended events are flushed to stable storage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncPolicy {
+    /// `fsync` after every event. Nothing acknowledged is ever lost.
+    Always,
+    /// `fsync` after every `n` events, and when a segment is closed.
+    EveryN(u32),
+    /// Leave flushing to the operating system.
+    Never,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JournalOptions {
+    /// Size after which the current segment is closed and a new one started.
+    pub segment_bytes: u64,
+    pub sync: SyncPolicy,
+}
+
+impl Default for JournalOptions {
+    fn default() -> Self {
+        JournalOptions {
+            segment_bytes: 8 << 20,
+            sync: SyncPolicy::Always,
+        }
+    }
+}
+
+struct Writer {
+    file:     File,
+    len:      u64,
+    next_seq: u64,
+    unsynced: u32,
+    /// Set when a failed write could not be cut back out of the file, or a
+    /// flush failed.
+    broken:   bool,
+}
+
+pub struct Journal<E, C = BinaryCodec> {
+    dir:     PathBuf,
+    options: JournalOptions,
+    codec:   C,
+    writer:  RefCell<Writer>,
+    error:   RefCell<Option<io::Error>>,
+    _event:  PhantomData<fn(&E)>,
+}
+
+impl<E> Journal<E>
+where
+    BinaryCodec: EventCodec<E>,
+{
+    /// Opens the journal in `dir`, creating the directory if needed and
+    /// recovering from an interrupted write.
+    pub fn open(
+        dir: impl Into<PathBuf>,
+        options: JournalOptions,
+    ) -> io::Result<Journal<E>> {
+        Journal::with_codec(dir, options, BinaryCodec)
+    }
+}
+
+impl<E, C: EventCodec<E>> Journal<E, C> {
+    pub fn with_codec(
+        dir: impl Into<PathBuf>,
+        options: JournalOptions,
+        codec: C,
+    ) -> io::Result<Journal<E, C>> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        let layout = Layout::scan(&dir)?;
+        // An interrupted compaction leaves segments the snapshot covers.
+        if let Some(snapshot) = layout.snapshot {
+            for &base in layout.segments.iter().filter(|&&b| b <= snapshot) {
+                fs::remove_file(segment_path(&dir, base))?;
+            }
+        }
+        let segments = layout.live_segments();
+
+        let writer = match segments.last() {
+            Some(&base) => {
+                for &sealed in &segments[..segments.len() - 1] {
+                    read_frames(&codec, &segment_path(&dir, sealed))?
+                        .into_events()?;
+                }
+                let path = segment_path(&dir, base);
+                let frames = read_frames(&codec, &path)?;
+                let file = OpenOptions::new().append(true).open(&path)?;
+                if frames.torn {
+                    file.set_len(frames.intact)?;
+                    file.sync_all()?;
+                } else if frames.damage.is_some() {
+                    return Err(frames.damage_error());
+                }
+                Writer {
+                    len: frames.intact,
+                    file,
+                    next_seq: base + frames.events.len() as u64,
+                    unsynced: 0,
+                    broken: false,
+                }
+            },
+            None => {
+                let base = layout.snapshot.map_or(0, |s| s + 1);
+                Writer {
+                    file:     create_segment(&dir, base)?,
+                    len:      0,
+                    next_seq: base,
+                    unsynced: 0,
+                    broken:   false,
+                }
+            },
+        };
+        Ok(Journal {
+            dir,
+            options,
+            codec,
+            writer: RefCell::new(writer),
+            error: RefCell::new(None),
+            _event: PhantomData,
+        })
+    }
+
+    /// Appends `event` and returns its sequence number.
+    pub fn append(&self, event: &E) -> io::Result<u64> {
+        let mut frame = Vec::new();
+        self.codec.encode(event, &mut frame)?;
+        let mut writer = self.writer.borrow_mut();
+        if writer.broken {
+            return Err(io::Error::other(
+                "journal is unusable after a failed write; reopen it",
+            ));
+        }
+        if writer.len > 0 && writer.len >= self.options.segment_bytes {
+            self.roll(&mut writer)?;
+        }
+        if let Err(err) = writer.file.write_all(&frame) {
+            // Drop whatever part of the frame was written.
+            let len = writer.len;
+            writer.broken = writer.file.set_len(len).is_err();
+            return Err(err);
+        }
+        // The frame is in the segment now, so it keeps its number even if
+        // the flush below fails.
+        let seq = writer.next_seq;
+        writer.next_seq += 1;
+        writer.len += frame.len() as u64;
+        writer.unsynced += 1;
+        let due = match self.options.sync {
+            SyncPolicy::Always => true,
+            SyncPolicy::EveryN(n) => writer.unsynced >= n,
+            SyncPolicy::Never => false,
+        };
+        if due {
+            if let Err(err) = writer.file.sync_data() {
+                writer.broken = true;
+                return Err(err);
+            }
+            writer.unsynced = 0;
+        }
+        Ok(seq)
+    }
+
+    /// Flushes everything appended so far to stable storage.
+    pub fn sync(&self) -> io::Result<()> {
+        let mut writer = self.writer.borrow_mut();
+        writer.file.sync_data()?;
+        writer.unsynced = 0;
+        Ok(())
+    }
+
+    /// Delivers the newest snapshot, if any, and every event after it, in
+    /// order. Returns how many events were delivered.
+    pub fn replay(&self, mut deliver: impl FnMut(E)) -> io::Result<u64> {
+        self.replay_from(0, |_, event| {
+            deliver(event);
+            ControlFlow::Continue(())
+        })
+    }
+
+    /// Like [`replay`](Self::replay), but skips events numbered below `from`
+    /// and passes each event's sequence number along. Stops without reading
+    /// further segments once `deliver` breaks.
+    pub fn replay_from(
+        &self,
+        from: u64,
+        mut deliver: impl FnMut(u64, E) -> ControlFlow<()>,
+    ) -> io::Result<u64> {
+        let layout = Layout::scan(&self.dir)?;
+        let mut files = Vec::new();
+        if let Some(snapshot) = layout.snapshot {
+            files.push((snapshot, snapshot_path(&self.dir, snapshot)));
+        }
+        files.extend(
+            layout
+                .live_segments()
+                .into_iter()
+                .map(|base| (base, segment_path(&self.dir, base))),
+        );
+        let mut count = 0;
+        for (idx, (base, path)) in files.iter().enumerate() {
+            // Files that end before `from` need not be read at all.
+            if files.get(idx + 1).is_some_and(|(next, _)| *next <= from) {
+                continue;
+            }
+            let events = read_frames(&self.codec, path)?.into_events()?;
+            for (seq, event) in (*base..).zip(events) {
+                if seq >= from {
+                    count += 1;
+                    if deliver(seq, event).is_break() {
+                        return Ok(count);
+                    }
+                }
+            }
+        }
+        Ok(count)
+    }
+
+    /// Replaces everything logged so far with `snapshot`, which must describe
+    /// the state those events built up.
+    pub fn compact(&self, snapshot: &E) -> io::Result<()> {
+        let mut frame = Vec::new();
+        self.codec.encode(snapshot, &mut frame)?;
+        let mut writer = self.writer.borrow_mut();
+        let seq = writer.next_seq;
+        let old = Layout::scan(&self.dir)?;
+
+        // Write the snapshot under a temporary name so a crash can never
+        // leave a half-written one behind.
+        let tmp = self.dir.join("snapshot.tmp");
+        let mut file = File::create(&tmp)?;
+        file.write_all(&frame)?;
+        file.sync_all()?;
+        fs::rename(&tmp, snapshot_path(&self.dir, seq))?;
+        sync_dir(&self.dir)?;
+
+        // Start the next segment after the snapshot before dropping the old
+        // files, then clean up.
+        writer.file = create_segment(&self.dir, seq + 1)?;
+        writer.len = 0;
+        writer.next_seq = seq + 1;
+        writer.unsynced = 0;
+        for base in old.segments {
+            fs::remove_file(segment_path(&self.dir, base))?;
+        }
+        if let Some(base) = old.snapshot {
+            fs::remove_file(snapshot_path(&self.dir, base))?;
+        }
+        sync_dir(&self.dir)
+    }
+
+    /// Deletes the segments that only hold events numbered below `seq`.
+    /// Returns how many were deleted. The segment being written is kept.
+    pub fn discard_before(&self, seq: u64) -> io::Result<usize> {
+        let segments = Layout::scan(&self.dir)?.live_segments();
+        let mut discarded = 0;
+        for pair in segments.windows(2) {
+            if pair[1] > seq {
+                break;
+            }
+            fs::remove_file(segment_path(&self.dir, pair[0]))?;
+            discarded += 1;
+        }
+        if discarded > 0 {
+            sync_dir(&self.dir)?;
+        }
+        Ok(discarded)
+    }
+
+    /// Sequence number the next appended event will get.
+    pub fn next_sequence(&self) -> u64 {
+        self.writer.borrow().next_seq
+    }
+
+    pub fn segment_count(&self) -> io::Result<usize> {
+        Ok(Layout::scan(&self.dir)?.segments.len())
+    }
+
+    /// Takes the first error hit while appending as an observer, since
+    /// [`IEventObserver::update`] has no way to return it.
+    pub fn take_error(&self) -> Option<io::Error> {
+        self.error.borrow_mut().take()
+    }
+
+    fn roll(&self, writer: &mut Writer) -> io::Result<()> {
+        if self.options.sync != SyncPolicy::Never {
+            writer.file.sync_data()?;
+        }
+        writer.file = create_segment(&self.dir, writer.next_seq)?;
+        writer.len = 0;
+        writer.unsynced = 0;
+        Ok(())
+    }
+}
+
+impl<E, C: EventCodec<E>> IEventObserver<E> for Journal<E, C> {
+    fn update(&self, event: &E) {
+        if let Err(err) = self.append(event) {
+            self.error.borrow_mut().get_or_insert(err);
+        }
+    }
+}
+
+impl<E, C> PartialEq for Journal<E, C> {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+struct Layout {
+    segments: Vec<u64>,
+    snapshot: Option<u64>,
+}
+
+impl Layout {
+    fn scan(dir: &Path) -> io::Result<Layout> {
+        let mut segments = Vec::new();
+        let mut snapshot = None;
+        for entry in fs::read_dir(dir)? {
+            let name = entry?.file_name();
+            let Some(name) = name.to_str() else { continue };
+            if let Some(base) = parse_name(name, ".log") {
+                segments.push(base);
+            } else if let Some(base) = parse_name(name, ".snapshot") {
+                snapshot = snapshot.max(Some(base));
+            }
+        }
+        segments.sort_unstable();
+        Ok(Layout { segments, snapshot })
+    }
+
+    fn live_segments(&self) -> Vec<u64> {
+        self.segments
+            .iter()
+            .copied()
+            .filter(|&base| self.snapshot.is_none_or(|s| base > s))
+            .collect()
+    }
+}
+
+fn parse_name(name: &str, extension: &str) -> Option<u64> {
+    name.strip_suffix(extension)?.parse().ok()
+}
+
+fn segment_path(dir: &Path, base: u64) -> PathBuf {
+    dir.join(format!("{base:020}.log"))
+}
+
+fn snapshot_path(dir: &Path, base: u64) -> PathBuf {
+    dir.join(format!("{base:020}.snapshot"))
+}
+
+fn create_segment(dir: &Path, base: u64) -> io::Result<File> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(segment_path(dir, base))?;
+    sync_dir(dir)?;
+    Ok(file)
+}
+
+// Makes file creations, renames and deletions in `dir` durable.
+pub(crate) fn sync_dir(dir: &Path) -> io::Result<()> {
+    File::open(dir)?.sync_all()
+}
+
+/// The readable part of a segment file.
+struct Frames<E> {
+    path:   PathBuf,
+    events: Vec<E>,
+    /// Length of the file up to the end of the last intact frame.
+    intact: u64,
+    /// Why reading stopped before the end of the file, if it did.
+    damage: Option<CodecError>,
+    /// Whether the damage is a partly written last frame: no intact frame
+    /// follows it.
+    torn:   bool,
+}
+
+impl<E> Frames<E> {
+    fn into_events(self) -> io::Result<Vec<E>> {
+        match self.damage {
+            None => Ok(self.events),
+            Some(_) => Err(self.damage_error()),
+        }
+    }
+
+    fn damage_error(&self) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{} is damaged after byte {}: {}",
+                self.path.display(),
+                self.intact,
+                self.damage.unwrap()
+            ),
+        )
+    }
+}
+
+fn read_frames<E, C: EventCodec<E>>(
+    codec: &C,
+    path: &Path,
+) -> io::Result<Frames<E>> {
+    let bytes = fs::read(path)?;
+    let mut events = Vec::new();
+    let mut at = 0;
+    let mut damage = None;
+    let mut torn = false;
+    while at < bytes.len() {
+        match codec.decode(&bytes[at..]) {
+            Ok((event, used)) => {
+                events.push(event);
+                at += used;
+            },
+            Err(err) => {
+                torn =
+                    matches!(err, CodecError::Truncated | CodecError::Checksum)
+                        && !frame_follows(codec, &bytes, at);
+                damage = Some(err);
+                break;
+            },
+        }
+    }
+    Ok(Frames {
+        path: path.to_path_buf(),
+        events,
+        intact: at as u64,
+        damage,
+        torn,
+    })
+}
+
+/// Whether an intact frame starts anywhere after the damaged one at `at`.
+fn frame_follows<E, C: EventCodec<E>>(
+    codec: &C,
+    bytes: &[u8],
+    at: usize,
+) -> bool {
+    (at + 1..bytes.len()).any(|start| codec.decode(&bytes[start..]).is_ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::codec::FieldReader;
+    use crate::codec::FieldWriter;
+    use crate::codec::Record;
+    use crate::event::EventSubject;
+    use crate::event::IEventSubject;
+
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> TempDir {
+            let path = std::env::temp_dir()
+                .join(format!("demo-journal-{}-{name}", std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            TempDir(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    enum Ledger {
+        Deposit(i64),
+        Balance(i64),
+    }
+
+    impl Record for Ledger {
+        const TAG: u32 = 0x4c44_4752;
+
+        fn write_fields(&self, fields: &mut FieldWriter) {
+            match self {
+                Ledger::Deposit(amount) => fields.field(1, amount),
+                Ledger::Balance(total) => fields.field(2, total),
+            };
+        }
+
+        fn read_fields(fields: &FieldReader) -> Result<Self, CodecError> {
+            match fields.optional(1)? {
+                Some(amount) => Ok(Ledger::Deposit(amount)),
+                None => Ok(Ledger::Balance(fields.get(2)?)),
+            }
+        }
+    }
+
+    #[derive(Default, PartialEq)]
+    struct Account {
+        balance: RefCell<i64>,
+    }
+
+    impl IEventObserver<Ledger> for Account {
+        fn update(&self, event: &Ledger) {
+            match event {
+                Ledger::Deposit(amount) => *self.balance.borrow_mut() += amount,
+                Ledger::Balance(total) => *self.balance.borrow_mut() = *total,
+            }
+        }
+    }
+
+    fn small_segments(sync: SyncPolicy) -> JournalOptions {
+        JournalOptions {
+            segment_bytes: 64,
+            sync,
+        }
+    }
+
+    fn replayed(journal: &Journal<Ledger>) -> Vec<Ledger> {
+        let mut events = Vec::new();
+        journal.replay(|event| events.push(event)).unwrap();
+        events
+    }
+
+    fn last_segment(dir: &Path) -> PathBuf {
+        let layout = Layout::scan(dir).unwrap();
+        segment_path(dir, *layout.segments.last().unwrap())
+    }
+
+    #[test]
+    fn test_rebuild_subject_after_restart() {
+        let dir = TempDir::new("rebuild");
+        {
+            let journal =
+                Journal::open(&dir.0, JournalOptions::default()).unwrap();
+            let mut subject = EventSubject::new();
+            subject.attach(&journal);
+            for amount in [10, 20, -5] {
+                subject.notify_observers(&Ledger::Deposit(amount));
+            }
+            assert!(journal.take_error().is_none());
+        }
+
+        let journal = Journal::open(&dir.0, JournalOptions::default()).unwrap();
+        let account = Account::default();
+        let mut subject = EventSubject::new();
+        subject.attach(&account);
+        let count = journal
+            .replay(|event| subject.notify_observers(&event))
+            .unwrap();
+
+        assert_eq!(count, 3);
+        assert_eq!(*account.balance.borrow(), 25);
+        assert_eq!(journal.next_sequence(), 3);
+    }
+
+    #[test]
+    fn test_segments_roll_over() {
+        let dir = TempDir::new("roll");
+        let journal =
+            Journal::open(&dir.0, small_segments(SyncPolicy::EveryN(3)))
+                .unwrap();
+        for amount in 0..20 {
+            assert_eq!(
+                journal.append(&Ledger::Deposit(amount)).unwrap(),
+                amount as u64
+            );
+        }
+        journal.sync().unwrap();
+
+        assert!(journal.segment_count().unwrap() > 1);
+        let expected: Vec<_> = (0..20).map(Ledger::Deposit).collect();
+        assert_eq!(replayed(&journal), expected);
+    }
+
+    #[test]
+    fn test_replay_from_stops_early() {
+        let dir = TempDir::new("stop");
+        let journal =
+            Journal::open(&dir.0, small_segments(SyncPolicy::Never)).unwrap();
+        for amount in 0..20 {
+            journal.append(&Ledger::Deposit(amount)).unwrap();
+        }
+        // Damage the last segment: stopping early must not even read it.
+        fs::write(last_segment(&dir.0), b"garbage").unwrap();
+
+        let mut seen = Vec::new();
+        let count = journal
+            .replay_from(1, |seq, _| {
+                seen.push(seq);
+                if seen.len() < 3 {
+                    ControlFlow::Continue(())
+                } else {
+                    ControlFlow::Break(())
+                }
+            })
+            .unwrap();
+
+        assert_eq!((count, seen), (3, vec![1, 2, 3]));
+        assert!(journal.replay(|_| {}).is_err());
+    }
+
+    #[test]
+    fn test_compaction_bounds_the_log() {
+        let dir = TempDir::new("compact");
+        let journal =
+            Journal::open(&dir.0, small_segments(SyncPolicy::Never)).unwrap();
+        for amount in 1..=10 {
+            journal.append(&Ledger::Deposit(amount)).unwrap();
+        }
+
+        journal.compact(&Ledger::Balance(55)).unwrap();
+        journal.append(&Ledger::Deposit(5)).unwrap();
+
+        assert_eq!(journal.segment_count().unwrap(), 1);
+        assert_eq!(
+            replayed(&journal),
+            vec![Ledger::Balance(55), Ledger::Deposit(5)]
+        );
+        // Sequence numbers keep counting across compaction and reopening.
+        drop(journal);
+        let journal = Journal::open(&dir.0, JournalOptions::default()).unwrap();
+        assert_eq!(journal.next_sequence(), 12);
+        assert_eq!(replayed(&journal).len(), 2);
+    }
+
+    #[test]
+    fn test_recovers_from_truncation_mid_record() {
+        let mut frame = Vec::new();
+        BinaryCodec.encode(&Ledger::Deposit(0), &mut frame).unwrap();
+
+        // Cut the last frame at every possible byte.
+        for cut in 1..frame.len() {
+            let dir = TempDir::new("truncate");
+            {
+                let journal =
+                    Journal::open(&dir.0, small_segments(SyncPolicy::Always))
+                        .unwrap();
+                for amount in 0..7 {
+                    journal.append(&Ledger::Deposit(amount)).unwrap();
+                }
+            }
+            let path = last_segment(&dir.0);
+            let len = fs::metadata(&path).unwrap().len();
+            OpenOptions::new()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_len(len - cut as u64)
+                .unwrap();
+
+            let journal =
+                Journal::open(&dir.0, small_segments(SyncPolicy::Always))
+                    .unwrap();
+            assert_eq!(journal.next_sequence(), 6, "cut {cut}");
+            journal.append(&Ledger::Deposit(100)).unwrap();
+
+            let mut expected: Vec<_> = (0..6).map(Ledger::Deposit).collect();
+            expected.push(Ledger::Deposit(100));
+            assert_eq!(replayed(&journal), expected, "cut {cut}");
+        }
+    }
+
+    #[test]
+    fn test_recovers_from_torn_write_in_last_segment() {
+        let dir = TempDir::new("torn");
+        {
+            let journal =
+                Journal::open(&dir.0, JournalOptions::default()).unwrap();
+            for amount in 0..4 {
+                journal.append(&Ledger::Deposit(amount)).unwrap();
+            }
+        }
+        // Scribble over the tail of the last record.
+        let path = last_segment(&dir.0);
+        let mut bytes = fs::read(&path).unwrap();
+        let at = bytes.len() - 2;
+        bytes[at] ^= 0xFF;
+        fs::write(&path, bytes).unwrap();
+
+        let journal = Journal::open(&dir.0, JournalOptions::default()).unwrap();
+
+        assert_eq!(
+            replayed(&journal),
+            (0..3).map(Ledger::Deposit).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_failed_flush_keeps_numbering() {
+        use std::os::fd::OwnedFd;
+        use std::os::unix::net::UnixStream;
+
+        let dir = TempDir::new("flush");
+        let journal =
+            Journal::open(&dir.0, small_segments(SyncPolicy::Always)).unwrap();
+        journal.append(&Ledger::Deposit(1)).unwrap();
+        // Sockets take writes but cannot be flushed.
+        let (socket, _peer) = UnixStream::pair().unwrap();
+        journal.writer.borrow_mut().file = File::from(OwnedFd::from(socket));
+
+        assert!(journal.append(&Ledger::Deposit(2)).is_err());
+        assert_eq!(journal.next_sequence(), 2);
+        let err = journal.append(&Ledger::Deposit(3)).unwrap_err();
+        assert!(err.to_string().contains("reopen"));
+        assert_eq!(journal.next_sequence(), 2);
+    }
+
+    #[test]
+    fn test_damage_mid_last_segment_is_an_error() {
+        let dir = TempDir::new("middle");
+        {
+            let journal =
+                Journal::open(&dir.0, JournalOptions::default()).unwrap();
+            for amount in 0..4 {
+                journal.append(&Ledger::Deposit(amount)).unwrap();
+            }
+        }
+        let mut frame = Vec::new();
+        BinaryCodec.encode(&Ledger::Deposit(0), &mut frame).unwrap();
+        // Scribble over the second of four frames, in its body and then in
+        // its length prefix.
+        let path = last_segment(&dir.0);
+        let original = fs::read(&path).unwrap();
+        for at in [frame.len() + 6, frame.len() + 1] {
+            let mut bytes = original.clone();
+            bytes[at] ^= 0xFF;
+            fs::write(&path, &bytes).unwrap();
+
+            let err =
+                Journal::<Ledger>::open(&dir.0, JournalOptions::default())
+                    .err()
+                    .unwrap();
+
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "byte {at}");
+            assert_eq!(fs::read(&path).unwrap(), bytes, "byte {at}");
+        }
+    }
+
+    #[test]
+    fn test_damage_in_sealed_segment_is_an_error() {
+        let dir = TempDir::new("sealed");
+        {
+            let journal =
+                Journal::open(&dir.0, small_segments(SyncPolicy::Never))
+                    .unwrap();
+            for amount in 0..20 {
+                journal.append(&Ledger::Deposit(amount)).unwrap();
+            }
+        }
+        let first = segment_path(&dir.0, 0);
+        let mut bytes = fs::read(&first).unwrap();
+        bytes[6] ^= 0xFF;
+        fs::write(&first, bytes).unwrap();
+
+        let err = Journal::<Ledger>::open(&dir.0, JournalOptions::default())
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,34 @@
+//! Observer pattern building blocks. Included to provide testing surface for
+//! repository infrastructure.
+
+pub mod batch;
+pub mod breaker;
+pub mod broadcast;
+pub mod capability;
+pub mod clock;
+pub mod codec;
+pub mod collections;
+pub mod envelope;
+pub mod event;
+pub mod hierarchy;
+pub mod history;
+pub mod inspect;
+pub mod intercept;
+#[cfg(unix)]
+pub mod ipc;
+pub mod journal;
+pub mod metrics;
+pub mod observable;
+pub mod observer;
+pub mod processor;
+pub mod quota;
+pub mod retry;
+mod rng;
+pub mod sharded;
+pub mod signals;
+pub mod spool;
+pub mod static_subject;
+pub mod throttle;
+pub mod topology;
+pub mod veto;
+pub mod window;
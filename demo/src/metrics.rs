@@ -0,0 +1,557 @@
+//! Counters and latency histograms in the Prometheus text format.
+//!
+//! A [`Registry`] holds metric families and renders them with
+//! [`render`](Registry::render) to any [`io::Write`], or with
+//! [`render_to_file`](Registry::render_to_file) for a scraper that reads
+//! files, such as node_exporter's textfile collector.
+//!
+//! [`SubjectMetrics`] fills a registry from what an
+//! [`InterceptedSubject`](crate::intercept::InterceptedSubject) reports for
+//! each notification:
+//!
+//! | metric                              | type      | labels              |
+//! |-------------------------------------|-----------|---------------------|
+//! | `observer_events_published_total`   | counter   | `subject`           |
+//! | `observer_events_dropped_total`     | counter   | `subject`           |
+//! | `observer_deliveries_total`         | counter   | `subject`,`observer`|
+//! | `observer_delivery_failures_total`  | counter   | `subject`,`observer`|
+//! | `observer_notify_latency_seconds`   | histogram | `subject`,`observer`|
+//!
+//! `observer` is the number of the [`ObserverId`] that
+//! [`attach`](crate::intercept::InterceptedSubject::attach) returned, so a
+//! series keeps following the same observer when others detach.
+//!
+//! [`ObserverId`]: crate::event::ObserverId
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::intercept::Delivery;
+
+/// Upper bounds, in seconds, of the latency histogram buckets.
+pub const LATENCY_BUCKETS: [f64; 10] =
+    [0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Counter,
+    Histogram,
+}
+
+impl Kind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Kind::Counter => "counter",
+            Kind::Histogram => "histogram",
+        }
+    }
+}
+
+type Labels = Vec<(String, String)>;
+
+#[derive(Debug)]
+enum Series {
+    Counter(u64),
+    Histogram {
+        /// Observations per bucket, not cumulative; the last one is `+Inf`.
+        buckets: Vec<u64>,
+        sum:     f64,
+        count:   u64,
+    },
+}
+
+#[derive(Debug)]
+struct Family {
+    help:   String,
+    kind:   Kind,
+    series: BTreeMap<Labels, Series>,
+}
+
+/// Metric families by name. Safe to share with a thread that renders them.
+#[derive(Debug, Default)]
+pub struct Registry {
+    families: Mutex<BTreeMap<String, Family>>,
+}
+
+impl Registry {
+    pub fn new() -> Registry {
+        Registry::default()
+    }
+
+    /// Adds `by` to a counter, creating it at zero first if needed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `name` is already a histogram.
+    pub fn add(
+        &self,
+        name: &str,
+        help: &str,
+        labels: &[(&str, &str)],
+        by: u64,
+    ) {
+        self.update(name, help, Kind::Counter, labels, |series| {
+            if let Series::Counter(n) = series {
+                *n += by;
+            }
+        });
+    }
+
+    /// Records one observation of `seconds` in a histogram with
+    /// [`LATENCY_BUCKETS`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `name` is already a counter.
+    pub fn observe(
+        &self,
+        name: &str,
+        help: &str,
+        labels: &[(&str, &str)],
+        seconds: f64,
+    ) {
+        self.update(name, help, Kind::Histogram, labels, |series| {
+            if let Series::Histogram {
+                buckets,
+                sum,
+                count,
+            } = series
+            {
+                let idx = LATENCY_BUCKETS.partition_point(|le| *le < seconds);
+                buckets[idx] += 1;
+                *sum += seconds;
+                *count += 1;
+            }
+        });
+    }
+
+    /// Current value of a counter, or `None` if it does not exist.
+    pub fn counter(&self, name: &str, labels: &[(&str, &str)]) -> Option<u64> {
+        let families = self.families.lock().unwrap();
+        match families.get(name)?.series.get(&owned(labels))? {
+            Series::Counter(n) => Some(*n),
+            Series::Histogram { .. } => None,
+        }
+    }
+
+    /// Writes every family in the Prometheus text exposition format,
+    /// families and series sorted by name and labels.
+    pub fn render(&self, out: &mut impl Write) -> io::Result<()> {
+        let families = self.families.lock().unwrap();
+        for (name, family) in families.iter() {
+            let kind = family.kind.as_str();
+            writeln!(out, "# HELP {name} {}", escape_help(&family.help))?;
+            writeln!(out, "# TYPE {name} {kind}")?;
+            for (labels, series) in family.series.iter() {
+                match series {
+                    Series::Counter(n) => {
+                        writeln!(out, "{name}{} {n}", format_labels(labels))?;
+                    },
+                    Series::Histogram {
+                        buckets,
+                        sum,
+                        count,
+                    } => {
+                        let bounds = LATENCY_BUCKETS
+                            .iter()
+                            .map(|le| le.to_string())
+                            .chain(["+Inf".to_string()]);
+                        let mut cumulative = 0;
+                        for (le, n) in bounds.zip(buckets) {
+                            cumulative += n;
+                            let mut labels = labels.clone();
+                            labels.push(("le".to_string(), le));
+                            let labels = format_labels(&labels);
+                            writeln!(
+                                out,
+                                "{name}_bucket{labels} {cumulative}"
+                            )?;
+                        }
+                        let labels = format_labels(labels);
+                        writeln!(out, "{name}_sum{labels} {sum}")?;
+                        writeln!(out, "{name}_count{labels} {count}")?;
+                    },
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Renders into `path`, replacing it in one step so a scraper never
+    /// reads half a file.
+    pub fn render_to_file(&self, path: &Path) -> io::Result<()> {
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let mut file = io::BufWriter::new(File::create(&tmp)?);
+        self.render(&mut file)?;
+        file.into_inner()?.sync_all()?;
+        fs::rename(&tmp, path)
+    }
+
+    fn update(
+        &self,
+        name: &str,
+        help: &str,
+        kind: Kind,
+        labels: &[(&str, &str)],
+        apply: impl FnOnce(&mut Series),
+    ) {
+        let mut families = self.families.lock().unwrap();
+        let family = families.entry(name.to_string()).or_insert_with(|| {
+            Family {
+                help: help.to_string(),
+                kind,
+                series: BTreeMap::new(),
+            }
+        });
+        if family.kind != kind {
+            let existing = family.kind.as_str();
+            // Unlock first so the registry stays usable for other threads.
+            drop(families);
+            panic!("metric {name} is a {existing}, not a {}", kind.as_str());
+        }
+        let series = family.series.entry(owned(labels)).or_insert_with(|| {
+            match kind {
+                Kind::Counter => Series::Counter(0),
+                Kind::Histogram => {
+                    Series::Histogram {
+                        buckets: vec![0; LATENCY_BUCKETS.len() + 1],
+                        sum:     0.0,
+                        count:   0,
+                    }
+                },
+            }
+        });
+        apply(series);
+    }
+}
+
+/// Sorted by name, so the order a caller lists labels in does not matter.
+fn owned(labels: &[(&str, &str)]) -> Labels {
+    let mut labels: Labels = labels
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+    labels.sort();
+    labels
+}
+
+fn format_labels(labels: &Labels) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+    let pairs: Vec<_> = labels
+        .iter()
+        .map(|(k, v)| format!("{k}=\"{}\"", escape_label(v)))
+        .collect();
+    format!("{{{}}}", pairs.join(","))
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn escape_help(help: &str) -> String {
+    help.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+/// Records the notifications of one subject into a [`Registry`].
+pub struct SubjectMetrics<'a> {
+    registry: &'a Registry,
+    subject:  String,
+}
+
+impl<'a> SubjectMetrics<'a> {
+    /// Labels everything with `subject` and creates the subject's counters
+    /// at zero, so they are rendered before the first event.
+    pub fn new(registry: &'a Registry, subject: &str) -> SubjectMetrics<'a> {
+        let metrics = SubjectMetrics {
+            registry,
+            subject: subject.to_string(),
+        };
+        metrics.published(0);
+        metrics.dropped(0);
+        metrics
+    }
+
+    /// Records the result of one
+    /// [`notify_observers`](crate::intercept::InterceptedSubject::notify_observers).
+    pub fn record(&self, delivery: &Delivery) {
+        self.published(1);
+        let outcomes = match delivery {
+            Delivery::Dropped { .. } => return self.dropped(1),
+            Delivery::Delivered(outcomes) => outcomes,
+        };
+        for outcome in outcomes {
+            let observer = outcome.observer.0.to_string();
+            let labels =
+                [("subject", &*self.subject), ("observer", &*observer)];
+            if outcome.result.is_ok() {
+                self.registry.add(
+                    "observer_deliveries_total",
+                    "Events an observer handled successfully.",
+                    &labels,
+                    1,
+                );
+            } else {
+                self.registry.add(
+                    "observer_delivery_failures_total",
+                    "Events an observer failed to handle.",
+                    &labels,
+                    1,
+                );
+            }
+            self.registry.observe(
+                "observer_notify_latency_seconds",
+                "Time an observer took to handle an event.",
+                &labels,
+                outcome.elapsed.as_secs_f64(),
+            );
+        }
+    }
+
+    fn published(&self, by: u64) {
+        self.registry.add(
+            "observer_events_published_total",
+            "Events passed to notify_observers.",
+            &[("subject", &self.subject)],
+            by,
+        );
+    }
+
+    fn dropped(&self, by: u64) {
+        self.registry.add(
+            "observer_events_dropped_total",
+            "Events an interceptor dropped before delivery.",
+            &[("subject", &self.subject)],
+            by,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::event::IFallibleObserver;
+    use crate::event::ObserverError;
+    use crate::intercept::Flow;
+    use crate::intercept::InterceptedSubject;
+    use crate::intercept::Interceptor;
+
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> TempDir {
+            let path = std::env::temp_dir()
+                .join(format!("demo-metrics-{}-{name}", std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            TempDir(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    // Takes `cost` of virtual time and fails on odd events.
+    struct Worker<'c> {
+        clock: &'c ManualClock,
+        cost:  Duration,
+    }
+
+    impl PartialEq for Worker<'_> {
+        fn eq(&self, other: &Self) -> bool {
+            std::ptr::eq(self, other)
+        }
+    }
+
+    impl IFallibleObserver<u32> for Worker<'_> {
+        fn try_update(&self, event: &u32) -> Result<(), ObserverError> {
+            self.clock.advance(self.cost);
+            if event % 2 == 1 {
+                Err("odd".into())
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    struct DropAbove(u32);
+
+    impl Interceptor<u32> for DropAbove {
+        fn before(&self, event: &mut u32) -> Flow {
+            if *event > self.0 {
+                Flow::Drop
+            } else {
+                Flow::Continue
+            }
+        }
+    }
+
+    fn render(registry: &Registry) -> String {
+        let mut out = Vec::new();
+        registry.render(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_subject_counters() {
+        let clock = ManualClock::new();
+        let registry = Registry::new();
+        let metrics = SubjectMetrics::new(&registry, "orders");
+        let fast = Worker {
+            clock: &clock,
+            cost:  Duration::from_micros(200),
+        };
+        let slow = Worker {
+            clock: &clock,
+            cost:  Duration::from_secs(2),
+        };
+        let limit = DropAbove(10);
+        let mut subject = InterceptedSubject::with_clock(&clock);
+        subject.attach(&fast);
+        subject.attach(&slow);
+        subject.intercept(0, &limit);
+
+        for n in [2, 3, 4, 50] {
+            metrics.record(&subject.notify_observers(&n));
+        }
+
+        let subject = [("subject", "orders")];
+        let first = [("subject", "orders"), ("observer", "0")];
+        let second = [("subject", "orders"), ("observer", "1")];
+        let count = |name, labels: &[_]| registry.counter(name, labels);
+        assert_eq!(count("observer_events_published_total", &subject), Some(4));
+        assert_eq!(count("observer_events_dropped_total", &subject), Some(1));
+        assert_eq!(count("observer_deliveries_total", &first), Some(2));
+        assert_eq!(count("observer_delivery_failures_total", &second), Some(1));
+
+        let text = render(&registry);
+        // Labels render sorted by name, `le` last.
+        let line = |suffix: &str, observer: &str, le: &str, value: u32| {
+            let name = "observer_notify_latency_seconds";
+            let labels = format!(r#"observer="{observer}",subject="orders""#);
+            format!("{name}_{suffix}{{{labels}{le}}} {value}\n")
+        };
+        // 200 us three times for the first, 2 s three times for the second.
+        assert!(text.contains(&line("bucket", "0", r#",le="0.0005""#, 3)));
+        assert!(text.contains(&line("bucket", "1", r#",le="1""#, 0)));
+        assert!(text.contains(&line("count", "1", "", 3)));
+        assert!(text.contains(&line("sum", "1", "", 6)));
+    }
+
+    #[test]
+    fn test_observer_label_survives_detach() {
+        let clock = ManualClock::new();
+        let registry = Registry::new();
+        let metrics = SubjectMetrics::new(&registry, "orders");
+        let worker = || {
+            Worker {
+                clock: &clock,
+                cost:  Duration::ZERO,
+            }
+        };
+        let (first, second) = (worker(), worker());
+        let mut subject = InterceptedSubject::with_clock(&clock);
+        subject.attach(&first);
+        let id = subject.attach(&second);
+        metrics.record(&subject.notify_observers(&2));
+
+        subject.detach(&first);
+        metrics.record(&subject.notify_observers(&4));
+
+        let label = id.0.to_string();
+        let count = |observer: &str| {
+            let labels = [("subject", "orders"), ("observer", observer)];
+            registry.counter("observer_deliveries_total", &labels)
+        };
+        assert_eq!((count("0"), count(&label)), (Some(1), Some(2)));
+    }
+
+    #[test]
+    fn test_render_format() {
+        let registry = Registry::new();
+        registry.add("jobs_total", "Jobs run.", &[("queue", "a\"b")], 2);
+        registry.add("jobs_total", "Jobs run.", &[("queue", "x")], 1);
+        registry.observe("wait_seconds", "Wait.\nIn seconds.", &[], 0.25);
+        registry.observe("wait_seconds", "Wait.\nIn seconds.", &[], 7.0);
+
+        assert_eq!(
+            render(&registry),
+            "# HELP jobs_total Jobs run.
+# TYPE jobs_total counter
+jobs_total{queue=\"a\\\"b\"} 2
+jobs_total{queue=\"x\"} 1
+# HELP wait_seconds Wait.\\nIn seconds.
+# TYPE wait_seconds histogram
+wait_seconds_bucket{le=\"0.0001\"} 0
+wait_seconds_bucket{le=\"0.0005\"} 0
+wait_seconds_bucket{le=\"0.001\"} 0
+wait_seconds_bucket{le=\"0.005\"} 0
+wait_seconds_bucket{le=\"0.01\"} 0
+wait_seconds_bucket{le=\"0.05\"} 0
+wait_seconds_bucket{le=\"0.1\"} 0
+wait_seconds_bucket{le=\"0.5\"} 1
+wait_seconds_bucket{le=\"1\"} 1
+wait_seconds_bucket{le=\"5\"} 1
+wait_seconds_bucket{le=\"+Inf\"} 2
+wait_seconds_sum 7.25
+wait_seconds_count 2
+"
+        );
+    }
+
+    #[test]
+    fn test_label_order_does_not_matter() {
+        let registry = Registry::new();
+        registry.add("jobs_total", "Jobs run.", &[("a", "1"), ("b", "2")], 1);
+        registry.add("jobs_total", "Jobs run.", &[("b", "2"), ("a", "1")], 2);
+
+        let labels = [("b", "2"), ("a", "1")];
+        assert_eq!(registry.counter("jobs_total", &labels), Some(3));
+        let text = render(&registry);
+        assert_eq!(text.lines().last(), Some(r#"jobs_total{a="1",b="2"} 3"#));
+    }
+
+    #[test]
+    #[should_panic(expected = "metric jobs_total is a counter, not a histogram")]
+    fn test_kind_mismatch_panics() {
+        let registry = Registry::new();
+        registry.add("jobs_total", "Jobs run.", &[], 1);
+
+        registry.observe("jobs_total", "Jobs run.", &[("queue", "x")], 0.5);
+    }
+
+    #[test]
+    fn test_render_to_file() {
+        let dir = TempDir::new("render");
+        let path = dir.0.join("observers.prom");
+        let registry = Registry::new();
+        SubjectMetrics::new(&registry, "idle");
+
+        registry.render_to_file(&path).unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), render(&registry));
+        assert!(
+            render(&registry).contains(
+                "observer_events_published_total{subject=\"idle\"} 0\n"
+            )
+        );
+        assert!(!dir.0.join("observers.prom.tmp").exists());
+    }
+}
@@ -0,0 +1,157 @@
+//! Observable value cell.
+//!
+//! [`Observable`] owns a single value and tells its observers whenever it
+//! changes, passing both the old and the new value. Writes that leave the
+//! value equal to what it was are not reported. Observers subscribe through
+//! the usual [`IEventSubject`] API.
+
+use crate::event::EventSubject;
+use crate::event::IEventObserver;
+use crate::event::IEventSubject;
+
+/// Notification sent by [`Observable`] when its value changes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change<T> {
+    pub old: T,
+    pub new: T,
+}
+
+pub struct Observable<'a, T, O: IEventObserver<Change<T>>> {
+    value:     T,
+    observers: EventSubject<'a, Change<T>, O>,
+}
+
+impl<'a, T, O> Observable<'a, T, O>
+where
+    T: Clone + PartialEq,
+    O: IEventObserver<Change<T>> + PartialEq,
+{
+    pub fn new(value: T) -> Observable<'a, T, O> {
+        Observable {
+            value,
+            observers: EventSubject::new(),
+        }
+    }
+
+    pub fn get(&self) -> &T {
+        &self.value
+    }
+
+    /// Stores `value`, notifying observers if it differs from the current
+    /// one. Returns whether a notification was sent.
+    pub fn set(&mut self, value: T) -> bool {
+        if value == self.value {
+            return false;
+        }
+        let old = std::mem::replace(&mut self.value, value);
+        self.notify_observers(&Change {
+            old,
+            new: self.value.clone(),
+        });
+        true
+    }
+
+    /// Modifies the value in place, notifying observers if `f` changed it.
+    /// Returns whether a notification was sent.
+    pub fn update(&mut self, f: impl FnOnce(&mut T)) -> bool {
+        let old = self.value.clone();
+        f(&mut self.value);
+        if old == self.value {
+            return false;
+        }
+        self.notify_observers(&Change {
+            old,
+            new: self.value.clone(),
+        });
+        true
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+impl<'a, T, O> IEventSubject<'a, Change<T>, O> for Observable<'a, T, O>
+where
+    T: Clone + PartialEq,
+    O: IEventObserver<Change<T>> + PartialEq,
+{
+    fn attach(&mut self, observer: &'a O) {
+        self.observers.attach(observer);
+    }
+    fn detach(&mut self, observer: &'a O) {
+        self.observers.detach(observer);
+    }
+    fn notify_observers(&self, event: &Change<T>) {
+        self.observers.notify_observers(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+
+    #[derive(PartialEq, Default)]
+    struct ChangeRecorder {
+        changes: RefCell<Vec<Change<i32>>>,
+    }
+
+    impl IEventObserver<Change<i32>> for ChangeRecorder {
+        fn update(&self, event: &Change<i32>) {
+            self.changes.borrow_mut().push(event.clone());
+        }
+    }
+
+    #[test]
+    fn test_set_notifies_old_and_new() {
+        let observer = ChangeRecorder::default();
+        let mut cell = Observable::new(1);
+        cell.attach(&observer);
+
+        assert!(cell.set(2));
+
+        assert_eq!(*cell.get(), 2);
+        assert_eq!(*observer.changes.borrow(), vec![Change { old: 1, new: 2 }]);
+    }
+
+    #[test]
+    fn test_unchanged_value_is_not_reported() {
+        let observer = ChangeRecorder::default();
+        let mut cell = Observable::new(5);
+        cell.attach(&observer);
+
+        assert!(!cell.set(5));
+        assert!(!cell.update(|v| *v *= 1));
+
+        assert!(observer.changes.borrow().is_empty());
+    }
+
+    #[test]
+    fn test_update_in_place() {
+        let observer = ChangeRecorder::default();
+        let mut cell = Observable::new(10);
+        cell.attach(&observer);
+
+        assert!(cell.update(|v| *v += 5));
+
+        assert_eq!(cell.into_inner(), 15);
+        assert_eq!(
+            *observer.changes.borrow(),
+            vec![Change { old: 10, new: 15 }]
+        );
+    }
+
+    #[test]
+    fn test_detach() {
+        let observer = ChangeRecorder::default();
+        let mut cell = Observable::new(0);
+        cell.attach(&observer);
+        cell.detach(&observer);
+
+        cell.set(1);
+
+        assert!(observer.changes.borrow().is_empty());
+    }
+}
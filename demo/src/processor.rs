@@ -0,0 +1,315 @@
+//! Relays that are both observer and subject.
+//!
+//! A [`Processor`] implements [`IEventObserver`] so it can be attached to an
+//! upstream subject, and [`IEventSubject`] so downstream observers can attach
+//! to it. Each incoming event goes through a transform that may change it or,
+//! by returning `None`, drop it.
+//!
+//! Downstream observers may be of any type, and processors are linked
+//! through `&self` so pipelines can be wired after they are shared. That
+//! also makes loops possible, so they are checked for twice:
+//!
+//! - [`Processor::pipe`] refuses a link that would close a loop between
+//!   processors and returns a [`CycleError`].
+//! - A processor that is handed an event while it is still delivering another,
+//!   because some hand-written observer fed it back in, drops the event instead
+//!   of recursing and counts it in
+//!   [`cycles_detected`](Processor::cycles_detected).
+
+use std::cell::Cell;
+use std::cell::RefCell;
+use std::error::Error;
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+
+use crate::event::IEventObserver;
+use crate::event::IEventSubject;
+
+// Lets a processor walk the processors downstream of it without knowing
+// their event types.
+trait Link {
+    fn id(&self) -> u64;
+    fn reaches(&self, id: u64) -> bool;
+}
+
+/// The transform is a type parameter rather than a boxed closure so that
+/// processors can borrow each other: a boxed closure could run code on drop,
+/// which the borrow checker will not allow for values linked in a loop.
+pub struct Processor<'a, In, Out, F> {
+    id: u64,
+    transform: F,
+    observers: RefCell<Vec<&'a dyn IEventObserver<Out>>>,
+    links: RefCell<Vec<&'a dyn Link>>,
+    active: Cell<bool>,
+    cycles: Cell<u64>,
+    _input: PhantomData<fn(&In)>,
+}
+
+impl<'a, In, Out, F: Fn(&In) -> Option<Out>> Processor<'a, In, Out, F> {
+    /// Creates a processor that forwards `transform(event)` when it returns
+    /// `Some` and drops the event otherwise.
+    pub fn new(transform: F) -> Processor<'a, In, Out, F> {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        Processor {
+            id: NEXT.fetch_add(1, Ordering::Relaxed),
+            transform,
+            observers: RefCell::new(Vec::new()),
+            links: RefCell::new(Vec::new()),
+            active: Cell::new(false),
+            cycles: Cell::new(0),
+            _input: PhantomData,
+        }
+    }
+
+    /// Adds a downstream observer through a shared reference.
+    pub fn connect(&self, observer: &'a dyn IEventObserver<Out>) {
+        self.observers.borrow_mut().push(observer);
+    }
+
+    /// Links `next` downstream of this processor, unless `next` already
+    /// leads back here.
+    pub fn pipe<Next, G>(
+        &self,
+        next: &'a Processor<'a, Out, Next, G>,
+    ) -> Result<(), CycleError>
+    where
+        G: Fn(&Out) -> Option<Next>,
+        Out: 'a,
+        Next: 'a,
+        G: 'a,
+    {
+        if next.id == self.id || next.reaches(self.id) {
+            return Err(CycleError);
+        }
+        self.links.borrow_mut().push(next);
+        self.connect(next);
+        Ok(())
+    }
+
+    /// Number of events dropped because they re-entered this processor.
+    pub fn cycles_detected(&self) -> u64 {
+        self.cycles.get()
+    }
+
+    pub fn len(&self) -> usize {
+        self.observers.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.observers.borrow().is_empty()
+    }
+
+    fn emit(&self, event: &Out) {
+        // Clone the list so observers may connect more observers while
+        // being notified.
+        let observers = self.observers.borrow().clone();
+        for item in observers {
+            item.update(event);
+        }
+    }
+}
+
+/// Creates a processor that transforms every event with `f`.
+pub fn map<'a, In, Out, G: Fn(&In) -> Out>(
+    f: G,
+) -> Processor<'a, In, Out, impl Fn(&In) -> Option<Out> + use<In, Out, G>> {
+    Processor::new(move |event| Some(f(event)))
+}
+
+impl<In, Out, F> Link for Processor<'_, In, Out, F> {
+    fn id(&self) -> u64 {
+        self.id
+    }
+
+    fn reaches(&self, id: u64) -> bool {
+        self.links
+            .borrow()
+            .iter()
+            .any(|link| link.id() == id || link.reaches(id))
+    }
+}
+
+impl<In, Out, F> IEventObserver<In> for Processor<'_, In, Out, F>
+where
+    F: Fn(&In) -> Option<Out>,
+{
+    fn update(&self, event: &In) {
+        if self.active.replace(true) {
+            self.cycles.set(self.cycles.get() + 1);
+            return;
+        }
+        let _reset = ActiveScope(&self.active);
+        if let Some(out) = (self.transform)(event) {
+            self.emit(&out);
+        }
+    }
+}
+
+impl<'a, In, Out, F, T> IEventSubject<'a, Out, T> for Processor<'a, In, Out, F>
+where
+    F: Fn(&In) -> Option<Out>,
+    T: IEventObserver<Out> + 'a,
+{
+    fn attach(&mut self, observer: &'a T) {
+        self.connect(observer);
+    }
+    fn detach(&mut self, observer: &'a T) {
+        let observers = self.observers.get_mut();
+        if let Some(idx) = observers
+            .iter()
+            .position(|x| std::ptr::addr_eq(*x, observer))
+        {
+            observers.remove(idx);
+        }
+        self.links
+            .get_mut()
+            .retain(|link| !std::ptr::addr_eq(*link, observer));
+    }
+    fn notify_observers(&self, event: &Out) {
+        self.emit(event);
+    }
+}
+
+impl<In, Out, F> PartialEq for Processor<'_, In, Out, F> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+struct ActiveScope<'c>(&'c Cell<bool>);
+
+impl Drop for ActiveScope<'_> {
+    fn drop(&mut self) {
+        self.0.set(false);
+    }
+}
+
+/// Returned by [`Processor::pipe`] when the link would form a loop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CycleError;
+
+impl fmt::Display for CycleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "linking these processors would create a cycle")
+    }
+}
+
+impl Error for CycleError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::EventSubject;
+
+    #[derive(PartialEq, Default)]
+    struct Recorder<E> {
+        seen: RefCell<Vec<E>>,
+    }
+
+    impl<E: Clone> IEventObserver<E> for Recorder<E> {
+        fn update(&self, event: &E) {
+            self.seen.borrow_mut().push(event.clone());
+        }
+    }
+
+    #[test]
+    fn test_transform_between_subjects() {
+        let sink = Recorder::default();
+        let mut to_text = map(|n: &i32| format!("#{n}"));
+        to_text.attach(&sink);
+        let mut source = EventSubject::new();
+        source.attach(&to_text);
+
+        source.notify_observers(&1);
+        source.notify_observers(&2);
+
+        assert_eq!(*sink.seen.borrow(), vec!["#1", "#2"]);
+    }
+
+    #[test]
+    fn test_filter_drops_events() {
+        let sink = Recorder::default();
+        let evens =
+            Processor::new(|n: &i32| if n % 2 == 0 { Some(*n) } else { None });
+        evens.connect(&sink);
+
+        for n in 0..6 {
+            evens.update(&n);
+        }
+
+        assert_eq!(*sink.seen.borrow(), vec![0, 2, 4]);
+    }
+
+    #[test]
+    fn test_pipeline_of_processors() {
+        let sink = Recorder::default();
+        let double = map(|n: &i32| n * 2);
+        let describe = map(|n: &i32| n.to_string());
+        let shout = map(|s: &String| format!("{s}!"));
+        double.pipe(&describe).unwrap();
+        describe.pipe(&shout).unwrap();
+        shout.connect(&sink);
+
+        double.update(&21);
+
+        assert_eq!(*sink.seen.borrow(), vec!["42!"]);
+    }
+
+    #[test]
+    fn test_pipe_rejects_cycles() {
+        let a = map(|n: &i32| n + 1);
+        let b = map(|n: &i32| n + 1);
+        let c = map(|n: &i32| n + 1);
+
+        assert_eq!(a.pipe(&a), Err(CycleError));
+        a.pipe(&b).unwrap();
+        b.pipe(&c).unwrap();
+        assert_eq!(c.pipe(&a), Err(CycleError));
+        assert_eq!(c.pipe(&b), Err(CycleError));
+        // A diamond is not a cycle.
+        a.pipe(&c).unwrap();
+    }
+
+    #[test]
+    fn test_runtime_reentry_is_caught() {
+        // Feeds everything it sees back into the processor by hand.
+        struct Feedback<'p> {
+            into: &'p dyn IEventObserver<i32>,
+            seen: Cell<u32>,
+        }
+        impl IEventObserver<i32> for Feedback<'_> {
+            fn update(&self, event: &i32) {
+                self.seen.set(self.seen.get() + 1);
+                self.into.update(event);
+            }
+        }
+
+        let relay = map(|n: &i32| *n);
+        let feedback = Feedback {
+            into: &relay,
+            seen: Cell::new(0),
+        };
+        relay.connect(&feedback);
+
+        relay.update(&1);
+        relay.update(&2);
+
+        assert_eq!(feedback.seen.get(), 2);
+        assert_eq!(relay.cycles_detected(), 2);
+    }
+
+    #[test]
+    fn test_detach() {
+        let sink = Recorder::default();
+        let mut relay = map(|n: &i32| *n);
+        relay.attach(&sink);
+        relay.detach(&sink);
+
+        relay.update(&1);
+
+        assert!(relay.is_empty());
+        assert!(sink.seen.borrow().is_empty());
+    }
+}
@@ -0,0 +1,381 @@
+//! A subject with limits on how far it can grow.
+//!
+//! A [`QuotaSubject`] refuses, with a [`QuotaError`], to:
+//!
+//! - attach more than [`Quotas::max_observers`] observers in total;
+//! - attach more than [`Quotas::max_per_owner`] observers under one owner tag;
+//! - deliver an event larger than the limit set with
+//!   [`with_payload_limit`](QuotaSubject::with_payload_limit).
+//!
+//! Before a limit is reached, it sends a [`QuotaWarning`] to the observer set
+//! with [`on_warning`](QuotaSubject::on_warning) once usage reaches
+//! [`Quotas::warn_ratio`] of the limit. Observer counts warn once per
+//! crossing: a count must fall back below the threshold before it warns
+//! again. Payloads warn for every event that is close to the limit.
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+
+use crate::event::IEventObserver;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quotas {
+    pub max_observers: Option<usize>,
+    pub max_per_owner: Option<usize>,
+    /// Fraction of a limit at which to warn, between 0 and 1.
+    pub warn_ratio:    f64,
+}
+
+impl Default for Quotas {
+    fn default() -> Self {
+        Quotas {
+            max_observers: None,
+            max_per_owner: None,
+            warn_ratio:    0.8,
+        }
+    }
+}
+
+impl Quotas {
+    fn near(&self, used: usize, limit: usize) -> bool {
+        used as f64 >= limit as f64 * self.warn_ratio
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QuotaWarning {
+    Observers {
+        count: usize,
+        limit: usize,
+    },
+    Owner {
+        owner: String,
+        count: usize,
+        limit: usize,
+    },
+    Payload {
+        size:  usize,
+        limit: usize,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QuotaError {
+    TooManyObservers { limit: usize },
+    TooManyForOwner { owner: String, limit: usize },
+    PayloadTooLarge { size: usize, limit: usize },
+}
+
+impl fmt::Display for QuotaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QuotaError::TooManyObservers { limit } => {
+                write!(f, "subject already has {limit} observers")
+            },
+            QuotaError::TooManyForOwner { owner, limit } => {
+                write!(f, "owner {owner:?} already has {limit} observers")
+            },
+            QuotaError::PayloadTooLarge { size, limit } => {
+                write!(f, "payload of {size} bytes exceeds {limit} bytes")
+            },
+        }
+    }
+}
+
+impl Error for QuotaError {}
+
+struct PayloadLimit<'a, E> {
+    max:  usize,
+    size: Box<dyn Fn(&E) -> usize + 'a>,
+}
+
+pub struct QuotaSubject<'a, E, T> {
+    observers: Vec<(&'a T, String)>,
+    per_owner: HashMap<String, usize>,
+    quotas: Quotas,
+    payload: Option<PayloadLimit<'a, E>>,
+    warnings: Option<&'a dyn IEventObserver<QuotaWarning>>,
+    /// Whether the total count has warned since it was last below the
+    /// threshold.
+    warned: bool,
+    /// Owners whose count has warned likewise.
+    warned_owners: HashSet<String>,
+}
+
+impl<'a, E, T: IEventObserver<E> + PartialEq> QuotaSubject<'a, E, T> {
+    pub fn new(quotas: Quotas) -> QuotaSubject<'a, E, T> {
+        QuotaSubject {
+            observers: Vec::new(),
+            per_owner: HashMap::new(),
+            quotas,
+            payload: None,
+            warnings: None,
+            warned: false,
+            warned_owners: HashSet::new(),
+        }
+    }
+
+    /// Refuses events for which `size` returns more than `max` bytes.
+    pub fn with_payload_limit(
+        mut self,
+        max: usize,
+        size: impl Fn(&E) -> usize + 'a,
+    ) -> QuotaSubject<'a, E, T> {
+        self.payload = Some(PayloadLimit {
+            max,
+            size: Box::new(size),
+        });
+        self
+    }
+
+    /// Sends warnings about limits that are close to `observer`.
+    pub fn on_warning(
+        &mut self,
+        observer: &'a dyn IEventObserver<QuotaWarning>,
+    ) {
+        self.warnings = Some(observer);
+    }
+
+    /// Attaches `observer` on behalf of `owner`, unless that would exceed a
+    /// limit.
+    pub fn attach(
+        &mut self,
+        owner: &str,
+        observer: &'a T,
+    ) -> Result<(), QuotaError> {
+        let owned = self.count_for(owner);
+        if let Some(limit) = self.quotas.max_observers
+            && self.observers.len() >= limit
+        {
+            return Err(QuotaError::TooManyObservers { limit });
+        }
+        if let Some(limit) = self.quotas.max_per_owner
+            && owned >= limit
+        {
+            return Err(QuotaError::TooManyForOwner {
+                owner: owner.to_string(),
+                limit,
+            });
+        }
+        self.observers.push((observer, owner.to_string()));
+        *self.per_owner.entry(owner.to_string()).or_default() += 1;
+
+        let count = self.observers.len();
+        if let Some(limit) = self.quotas.max_observers
+            && !self.warned
+            && self.quotas.near(count, limit)
+        {
+            self.warned = true;
+            self.warn(QuotaWarning::Observers { count, limit });
+        }
+        if let Some(limit) = self.quotas.max_per_owner
+            && !self.warned_owners.contains(owner)
+            && self.quotas.near(owned + 1, limit)
+        {
+            self.warned_owners.insert(owner.to_string());
+            self.warn(QuotaWarning::Owner {
+                owner: owner.to_string(),
+                count: owned + 1,
+                limit,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn detach(&mut self, observer: &'a T) {
+        let Some(idx) = self.observers.iter().position(|(x, _)| *x == observer)
+        else {
+            return;
+        };
+        let (_, owner) = self.observers.remove(idx);
+        let owned = self.per_owner.get_mut(&owner).expect("owner is counted");
+        *owned -= 1;
+        let owned = *owned;
+        if owned == 0 {
+            self.per_owner.remove(&owner);
+        }
+        if let Some(limit) = self.quotas.max_observers
+            && !self.quotas.near(self.observers.len(), limit)
+        {
+            self.warned = false;
+        }
+        if let Some(limit) = self.quotas.max_per_owner
+            && !self.quotas.near(owned, limit)
+        {
+            self.warned_owners.remove(&owner);
+        }
+    }
+
+    /// Delivers `event` to every observer, unless it is over the payload
+    /// limit.
+    pub fn notify_observers(&self, event: &E) -> Result<(), QuotaError> {
+        if let Some(payload) = &self.payload {
+            let size = (payload.size)(event);
+            let limit = payload.max;
+            if size > limit {
+                return Err(QuotaError::PayloadTooLarge { size, limit });
+            }
+            if self.quotas.near(size, limit) {
+                self.warn(QuotaWarning::Payload { size, limit });
+            }
+        }
+        for (item, _) in self.observers.iter() {
+            item.update(event);
+        }
+        Ok(())
+    }
+
+    /// Number of observers attached on behalf of `owner`.
+    pub fn count_for(&self, owner: &str) -> usize {
+        self.per_owner.get(owner).copied().unwrap_or(0)
+    }
+
+    pub fn len(&self) -> usize {
+        self.observers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.observers.is_empty()
+    }
+
+    fn warn(&self, warning: QuotaWarning) {
+        if let Some(warnings) = self.warnings {
+            warnings.update(&warning);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::cell::RefCell;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct Counter {
+        calls: Cell<u32>,
+    }
+
+    impl PartialEq for Counter {
+        fn eq(&self, other: &Self) -> bool {
+            std::ptr::eq(self, other)
+        }
+    }
+
+    impl IEventObserver<String> for Counter {
+        fn update(&self, _event: &String) {
+            self.calls.set(self.calls.get() + 1);
+        }
+    }
+
+    #[derive(Default)]
+    struct Warnings {
+        seen: RefCell<Vec<QuotaWarning>>,
+    }
+
+    impl IEventObserver<QuotaWarning> for Warnings {
+        fn update(&self, event: &QuotaWarning) {
+            self.seen.borrow_mut().push(event.clone());
+        }
+    }
+
+    fn quotas(max_observers: usize, max_per_owner: usize) -> Quotas {
+        Quotas {
+            max_observers: Some(max_observers),
+            max_per_owner: Some(max_per_owner),
+            warn_ratio:    0.75,
+        }
+    }
+
+    #[test]
+    fn test_total_limit() {
+        let observers: Vec<Counter> =
+            (0..5).map(|_| Counter::default()).collect();
+        let mut subject = QuotaSubject::new(quotas(4, 10));
+
+        for observer in &observers[..4] {
+            subject.attach("app", observer).unwrap();
+        }
+        let refused = subject.attach("app", &observers[4]);
+
+        assert_eq!(refused, Err(QuotaError::TooManyObservers { limit: 4 }));
+        assert_eq!(subject.len(), 4);
+        subject.detach(&observers[0]);
+        assert_eq!(subject.attach("app", &observers[4]), Ok(()));
+    }
+
+    #[test]
+    fn test_per_owner_limit() {
+        let observers: Vec<Counter> =
+            (0..4).map(|_| Counter::default()).collect();
+        let mut subject = QuotaSubject::new(quotas(10, 2));
+
+        subject.attach("plugin", &observers[0]).unwrap();
+        subject.attach("plugin", &observers[1]).unwrap();
+        let refused = subject.attach("plugin", &observers[2]).unwrap_err();
+        subject.attach("core", &observers[3]).unwrap();
+
+        assert_eq!(
+            refused.to_string(),
+            "owner \"plugin\" already has 2 observers"
+        );
+        assert_eq!(subject.count_for("plugin"), 2);
+        assert_eq!(subject.count_for("core"), 1);
+        assert_eq!(subject.len(), 3);
+    }
+
+    #[test]
+    fn test_warns_once_per_crossing() {
+        let observers: Vec<Counter> =
+            (0..4).map(|_| Counter::default()).collect();
+        let warnings = Warnings::default();
+        let mut subject = QuotaSubject::new(quotas(4, 10));
+        subject.on_warning(&warnings);
+
+        // 3 of 4 reaches the 75% threshold.
+        for observer in &observers {
+            subject.attach("app", observer).unwrap();
+        }
+        subject.detach(&observers[3]);
+        subject.detach(&observers[2]);
+        subject.attach("app", &observers[2]).unwrap();
+
+        assert_eq!(
+            *warnings.seen.borrow(),
+            vec![
+                QuotaWarning::Observers { count: 3, limit: 4 },
+                QuotaWarning::Observers { count: 3, limit: 4 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_payload_limit() {
+        let observer = Counter::default();
+        let warnings = Warnings::default();
+        let mut subject = QuotaSubject::new(Quotas::default())
+            .with_payload_limit(8, |s: &String| s.len());
+        subject.on_warning(&warnings);
+        subject.attach("app", &observer).unwrap();
+
+        subject.notify_observers(&"tiny".to_string()).unwrap();
+        subject.notify_observers(&"7 bytes".to_string()).unwrap();
+        let refused = subject.notify_observers(&"way too big".to_string());
+
+        assert_eq!(
+            refused,
+            Err(QuotaError::PayloadTooLarge {
+                size:  11,
+                limit: 8,
+            })
+        );
+        assert_eq!(observer.calls.get(), 2);
+        assert_eq!(
+            *warnings.seen.borrow(),
+            vec![QuotaWarning::Payload { size: 7, limit: 8 }]
+        );
+    }
+}
@@ -0,0 +1,385 @@
+//! Retrying failed deliveries and parking events that keep failing.
+//!
+//! A [`RetryingSubject`] notifies [`IFallibleObserver`]s. When one returns an
+//! error the delivery is retried according to the subject's [`RetryPolicy`],
+//! waiting between attempts on the subject's [`Sleeper`]. Once the attempts
+//! run out the event is recorded as a [`DeadLetter`] and the subject moves on
+//! to the next observer, so one failing observer does not hold up the rest.
+//!
+//! Dead letters can be inspected, delivered again with
+//! [`replay_dead_letters`](RetryingSubject::replay_dead_letters) once the
+//! observer has recovered, or dropped with
+//! [`purge_dead_letters`](RetryingSubject::purge_dead_letters).
+
+use std::cell::Cell;
+use std::cell::Ref;
+use std::cell::RefCell;
+use std::time::Duration;
+
+use crate::clock::Sleeper;
+use crate::clock::SystemClock;
+use crate::event::IFallibleObserver;
+use crate::event::ObserverError;
+use crate::rng::Rng;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backoff {
+    /// The same delay before every retry.
+    Fixed(Duration),
+    /// `initial` before the first retry, doubling for each one after, but
+    /// never more than `max`.
+    Exponential {
+        initial: Duration,
+        max:     Duration,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    /// Attempts per delivery, the first one included.
+    pub max_attempts: u32,
+    pub backoff: Backoff,
+    /// Fraction of each delay, from 0 to 1, that is randomly taken off so
+    /// that observers failing together do not retry in lockstep.
+    pub jitter: f64,
+}
+
+impl RetryPolicy {
+    /// Gives up after the first failure.
+    pub fn none() -> RetryPolicy {
+        RetryPolicy::fixed(1, Duration::ZERO)
+    }
+
+    pub fn fixed(max_attempts: u32, delay: Duration) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: max_attempts.max(1),
+            backoff: Backoff::Fixed(delay),
+            jitter: 0.0,
+        }
+    }
+
+    pub fn exponential(
+        max_attempts: u32,
+        initial: Duration,
+        max: Duration,
+    ) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: max_attempts.max(1),
+            backoff: Backoff::Exponential { initial, max },
+            jitter: 0.0,
+        }
+    }
+
+    pub fn with_jitter(mut self, fraction: f64) -> RetryPolicy {
+        self.jitter = fraction.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Delay before retry number `retry`, counting from 1, given a random
+    /// number in `[0, 1)`.
+    pub fn delay(&self, retry: u32, random: f64) -> Duration {
+        let delay = match self.backoff {
+            Backoff::Fixed(delay) => delay,
+            Backoff::Exponential { initial, max } => {
+                let factor = 1u32.checked_shl(retry.saturating_sub(1));
+                factor
+                    .and_then(|factor| initial.checked_mul(factor))
+                    .map_or(max, |delay| delay.min(max))
+            },
+        };
+        delay.mul_f64(1.0 - self.jitter * random)
+    }
+}
+
+/// An event an observer failed to handle on every attempt.
+pub struct DeadLetter<'a, E, T> {
+    pub event: E,
+    pub observer: &'a T,
+    pub attempts: u32,
+    /// Error from the last attempt.
+    pub error: ObserverError,
+    /// When the last attempt failed, by the subject's clock.
+    pub at: Duration,
+}
+
+pub struct RetryingSubject<'a, E, T, C = SystemClock> {
+    observers: Vec<&'a T>,
+    policy: RetryPolicy,
+    clock: C,
+    rng: Rng,
+    dead_letters: RefCell<Vec<DeadLetter<'a, E, T>>>,
+    retried: Cell<u64>,
+    dead_lettered: Cell<u64>,
+}
+
+impl<'a, E, T> RetryingSubject<'a, E, T>
+where
+    E: Clone,
+    T: IFallibleObserver<E> + PartialEq,
+{
+    pub fn new(policy: RetryPolicy) -> RetryingSubject<'a, E, T> {
+        RetryingSubject::with_clock(policy, SystemClock::new())
+    }
+}
+
+impl<'a, E, T, C> RetryingSubject<'a, E, T, C>
+where
+    E: Clone,
+    T: IFallibleObserver<E> + PartialEq,
+    C: Sleeper,
+{
+    pub fn with_clock(
+        policy: RetryPolicy,
+        clock: C,
+    ) -> RetryingSubject<'a, E, T, C> {
+        RetryingSubject {
+            observers: Vec::new(),
+            policy,
+            clock,
+            rng: Rng::new(),
+            dead_letters: RefCell::new(Vec::new()),
+            retried: Cell::new(0),
+            dead_lettered: Cell::new(0),
+        }
+    }
+
+    /// Makes jitter reproducible.
+    pub fn with_seed(mut self, seed: u64) -> RetryingSubject<'a, E, T, C> {
+        self.rng = Rng::seeded(seed);
+        self
+    }
+
+    pub fn attach(&mut self, observer: &'a T) {
+        self.observers.push(observer);
+    }
+
+    pub fn detach(&mut self, observer: &'a T) {
+        if let Some(idx) = self.observers.iter().position(|x| *x == observer) {
+            self.observers.remove(idx);
+        }
+    }
+
+    /// Delivers `event` to every observer, retrying failures and
+    /// dead-lettering the ones that never succeed.
+    pub fn notify_observers(&self, event: &E) {
+        for &item in self.observers.iter() {
+            self.deliver(item, event);
+        }
+    }
+
+    /// Delivers every dead letter again, with the same retry policy, and
+    /// returns how many went through. Letters that fail again stay queued.
+    pub fn replay_dead_letters(&self) -> usize {
+        let letters = self.dead_letters.take();
+        let before = self.dead_lettered.get();
+        let total = letters.len();
+        for letter in letters {
+            self.deliver(letter.observer, &letter.event);
+        }
+        // A letter that fails again is not a new dead letter.
+        let failed = self.dead_lettered.replace(before) - before;
+        total - failed as usize
+    }
+
+    /// Drops every dead letter and returns how many there were.
+    pub fn purge_dead_letters(&self) -> usize {
+        self.dead_letters.take().len()
+    }
+
+    pub fn dead_letters(&self) -> Ref<'_, [DeadLetter<'a, E, T>]> {
+        Ref::map(self.dead_letters.borrow(), Vec::as_slice)
+    }
+
+    /// Deliveries that needed more than one attempt.
+    pub fn retried(&self) -> u64 {
+        self.retried.get()
+    }
+
+    /// Deliveries that failed on every attempt.
+    pub fn dead_lettered(&self) -> u64 {
+        self.dead_lettered.get()
+    }
+
+    pub fn len(&self) -> usize {
+        self.observers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.observers.is_empty()
+    }
+
+    fn deliver(&self, observer: &'a T, event: &E) {
+        let mut attempt = 1;
+        loop {
+            let error = match observer.try_update(event) {
+                Ok(()) => return,
+                Err(error) => error,
+            };
+            if attempt >= self.policy.max_attempts {
+                self.dead_lettered.set(self.dead_lettered.get() + 1);
+                self.dead_letters.borrow_mut().push(DeadLetter {
+                    event: event.clone(),
+                    observer,
+                    attempts: attempt,
+                    error,
+                    at: self.clock.now(),
+                });
+                return;
+            }
+            if attempt == 1 {
+                self.retried.set(self.retried.get() + 1);
+            }
+            self.clock
+                .sleep(self.policy.delay(attempt, self.rng.next_f64()));
+            attempt += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::Clock;
+    use crate::clock::ManualClock;
+
+    // Fails its first `failures` attempts, then succeeds.
+    struct Flaky {
+        failures: Cell<u32>,
+        attempts: Cell<u32>,
+        handled:  RefCell<Vec<u32>>,
+    }
+
+    impl Flaky {
+        fn new(failures: u32) -> Flaky {
+            Flaky {
+                failures: Cell::new(failures),
+                attempts: Cell::new(0),
+                handled:  RefCell::new(Vec::new()),
+            }
+        }
+    }
+
+    impl PartialEq for Flaky {
+        fn eq(&self, other: &Self) -> bool {
+            std::ptr::eq(self, other)
+        }
+    }
+
+    impl IFallibleObserver<u32> for Flaky {
+        fn try_update(&self, event: &u32) -> Result<(), ObserverError> {
+            self.attempts.set(self.attempts.get() + 1);
+            if self.failures.get() > 0 {
+                self.failures.set(self.failures.get() - 1);
+                return Err(format!("cannot handle {event}").into());
+            }
+            self.handled.borrow_mut().push(*event);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_retries_until_success() {
+        let clock = ManualClock::new();
+        let flaky = Flaky::new(2);
+        let steady = Flaky::new(0);
+        let policy = RetryPolicy::fixed(3, Duration::from_millis(100));
+        let mut subject = RetryingSubject::with_clock(policy, &clock);
+        subject.attach(&flaky);
+        subject.attach(&steady);
+
+        subject.notify_observers(&7);
+
+        assert_eq!(*flaky.handled.borrow(), vec![7]);
+        assert_eq!(flaky.attempts.get(), 3);
+        assert_eq!(*steady.handled.borrow(), vec![7]);
+        assert_eq!(clock.now(), Duration::from_millis(200));
+        assert_eq!(subject.retried(), 1);
+        assert_eq!(subject.dead_lettered(), 0);
+    }
+
+    #[test]
+    fn test_exponential_backoff_is_capped() {
+        let policy = RetryPolicy::exponential(
+            10,
+            Duration::from_millis(10),
+            Duration::from_millis(50),
+        );
+        let delays: Vec<_> = (1..=5)
+            .map(|retry| policy.delay(retry, 0.5).as_millis())
+            .collect();
+
+        assert_eq!(delays, vec![10, 20, 40, 50, 50]);
+        assert_eq!(policy.delay(200, 0.0), Duration::from_millis(50));
+    }
+
+    #[test]
+    fn test_jitter_stays_within_bounds() {
+        let clock = ManualClock::new();
+        let never = Flaky::new(u32::MAX);
+        let policy =
+            RetryPolicy::fixed(101, Duration::from_millis(10)).with_jitter(0.5);
+        let mut subject =
+            RetryingSubject::with_clock(policy, &clock).with_seed(42);
+        subject.attach(&never);
+
+        subject.notify_observers(&1);
+
+        // 100 retries of between 5 and 10 ms, and not all the same.
+        let waited = clock.now();
+        assert!(waited > Duration::from_millis(500), "{waited:?}");
+        assert!(waited < Duration::from_millis(1000), "{waited:?}");
+    }
+
+    #[test]
+    fn test_dead_letters_can_be_inspected_replayed_and_purged() {
+        let clock = ManualClock::new();
+        let broken = Flaky::new(4);
+        let policy = RetryPolicy::fixed(2, Duration::from_secs(1));
+        let mut subject = RetryingSubject::with_clock(policy, &clock);
+        subject.attach(&broken);
+
+        subject.notify_observers(&1);
+        subject.notify_observers(&2);
+
+        assert_eq!(subject.dead_lettered(), 2);
+        {
+            let letters = subject.dead_letters();
+            assert_eq!(letters.len(), 2);
+            assert_eq!(letters[0].event, 1);
+            assert_eq!(letters[0].attempts, 2);
+            assert!(std::ptr::eq(letters[0].observer, &broken));
+            assert_eq!(letters[0].error.to_string(), "cannot handle 1");
+            assert_eq!(letters[1].at, Duration::from_secs(2));
+        }
+
+        // The observer has recovered by now.
+        assert_eq!(subject.replay_dead_letters(), 2);
+        assert_eq!(*broken.handled.borrow(), vec![1, 2]);
+        assert!(subject.dead_letters().is_empty());
+
+        broken.failures.set(2);
+        subject.notify_observers(&3);
+        assert_eq!(subject.purge_dead_letters(), 1);
+        assert!(subject.dead_letters().is_empty());
+        assert_eq!(subject.dead_lettered(), 3);
+    }
+
+    #[test]
+    fn test_replay_keeps_letters_that_fail_again() {
+        let clock = ManualClock::new();
+        let broken = Flaky::new(3);
+        let mut subject =
+            RetryingSubject::with_clock(RetryPolicy::none(), &clock);
+        subject.attach(&broken);
+        subject.notify_observers(&1);
+        subject.notify_observers(&2);
+
+        // One failure left: the first replayed letter fails, the second works.
+        assert_eq!(subject.replay_dead_letters(), 1);
+
+        assert_eq!(subject.dead_letters().len(), 1);
+        assert_eq!(subject.dead_letters()[0].event, 1);
+        assert_eq!(subject.dead_lettered(), 2);
+        assert_eq!(subject.retried(), 0);
+    }
+}
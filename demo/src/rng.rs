@@ -0,0 +1,44 @@
+//! Small non-cryptographic random source for jitter and sampling.
+
+use std::cell::Cell;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+
+/// xorshift64*, seeded from the standard library's per-process hash keys
+/// unless a seed is given.
+pub(crate) struct Rng {
+    state: Cell<u64>,
+}
+
+impl Rng {
+    pub(crate) fn new() -> Rng {
+        Rng::seeded(RandomState::new().hash_one(0u64))
+    }
+
+    pub(crate) fn seeded(seed: u64) -> Rng {
+        // Zero is the one state xorshift never leaves.
+        Rng {
+            state: Cell::new(seed | 1),
+        }
+    }
+
+    pub(crate) fn next_u64(&self) -> u64 {
+        let mut x = self.state.get();
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.state.set(x);
+        x.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// A number in `[0, 1)`.
+    pub(crate) fn next_f64(&self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// A number in `[0, n)`, slightly biased towards small numbers.
+    #[cfg(test)]
+    pub(crate) fn below(&self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
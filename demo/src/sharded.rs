@@ -0,0 +1,249 @@
+//! Subject that spreads its observers across shards notified in parallel.
+//!
+//! A single [`Subject`](crate::observer::Subject) walks one observer vector on
+//! the calling thread. [`ShardedSubject`] attaches each observer to whichever
+//! of its `N` shards holds the fewest, so shards stay balanced across attach
+//! and detach, and on [`notify_observers`](ISubject::notify_observers) hands
+//! each non-empty shard to its own scoped thread.
+//!
+//! # Ordering
+//!
+//! - Within a shard, observers are updated in the order they were attached,
+//!   exactly like `Subject`.
+//! - Across shards there is no ordering: updates on different shards run
+//!   concurrently and may interleave arbitrarily.
+//! - `notify_observers` returns only once every shard has finished, so all
+//!   updates for one notification happen before any update of the next.
+//! - An observer may be updated on any thread, hence the `Sync` bound.
+
+use std::num::NonZeroUsize;
+use std::thread;
+
+use crate::observer::IObserver;
+use crate::observer::ISubject;
+
+pub struct ShardedSubject<'a, T: IObserver> {
+    shards: Vec<Vec<&'a T>>,
+}
+
+impl<'a, T: IObserver + PartialEq + Sync> ShardedSubject<'a, T> {
+    /// Creates a subject with one shard per available CPU.
+    pub fn new() -> ShardedSubject<'a, T> {
+        let shards = thread::available_parallelism()
+            .map(NonZeroUsize::get)
+            .unwrap_or(1);
+        Self::with_shards(shards)
+    }
+
+    /// # Panics
+    ///
+    /// Panics if `shards` is zero.
+    pub fn with_shards(shards: usize) -> ShardedSubject<'a, T> {
+        assert!(shards > 0, "sharded subject needs at least one shard");
+        ShardedSubject {
+            shards: (0..shards).map(|_| Vec::new()).collect(),
+        }
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Number of observers attached to each shard.
+    pub fn shard_sizes(&self) -> Vec<usize> {
+        self.shards.iter().map(Vec::len).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(Vec::is_empty)
+    }
+}
+
+impl<'a, T: IObserver + PartialEq + Sync> Default for ShardedSubject<'a, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, T: IObserver + PartialEq + Sync> ISubject<'a, T>
+    for ShardedSubject<'a, T>
+{
+    fn attach(&mut self, observer: &'a T) {
+        // Ties go to the lowest index, so filling an empty subject is
+        // round-robin.
+        let smallest = self
+            .shards
+            .iter_mut()
+            .min_by_key(|shard| shard.len())
+            .expect("at least one shard");
+        smallest.push(observer);
+    }
+    fn detach(&mut self, observer: &'a T) {
+        for shard in self.shards.iter_mut() {
+            if let Some(idx) = shard.iter().position(|x| *x == observer) {
+                shard.remove(idx);
+                return;
+            }
+        }
+    }
+    fn notify_observers(&self) {
+        let mut busy = self.shards.iter().filter(|shard| !shard.is_empty());
+        let Some(first) = busy.next() else {
+            return;
+        };
+        thread::scope(|scope| {
+            for shard in busy {
+                scope.spawn(move || notify_shard(shard));
+            }
+            // The calling thread takes a shard instead of idling in the join.
+            notify_shard(first);
+        });
+    }
+}
+
+fn notify_shard<T: IObserver>(shard: &[&T]) {
+    for item in shard {
+        item.update();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::sync::Mutex;
+    use std::thread::ThreadId;
+
+    use super::*;
+
+    // Records every update into a log shared by all observers of a test.
+    struct LogObserver<'l> {
+        id:  usize,
+        log: &'l Mutex<Vec<(usize, ThreadId)>>,
+    }
+
+    impl PartialEq for LogObserver<'_> {
+        fn eq(&self, other: &Self) -> bool {
+            self.id == other.id
+        }
+    }
+
+    impl IObserver for LogObserver<'_> {
+        fn update(&self) {
+            let thread = thread::current().id();
+            self.log.lock().unwrap().push((self.id, thread));
+        }
+    }
+
+    fn observers(
+        count: usize,
+        log: &Mutex<Vec<(usize, ThreadId)>>,
+    ) -> Vec<LogObserver<'_>> {
+        (0..count).map(|id| LogObserver { id, log }).collect()
+    }
+
+    #[test]
+    fn test_attach_spreads_round_robin() {
+        let log = Mutex::new(Vec::new());
+        let observers = observers(10, &log);
+        let mut subject = ShardedSubject::with_shards(4);
+
+        for observer in &observers {
+            subject.attach(observer);
+        }
+
+        assert_eq!(subject.shard_sizes(), vec![3, 3, 2, 2]);
+        assert_eq!(subject.len(), 10);
+    }
+
+    #[test]
+    fn test_every_observer_notified_once() {
+        let log = Mutex::new(Vec::new());
+        let observers = observers(100, &log);
+        let mut subject = ShardedSubject::with_shards(4);
+        for observer in &observers {
+            subject.attach(observer);
+        }
+
+        subject.notify_observers();
+
+        let mut ids: Vec<_> = log.lock().unwrap().iter().map(|e| e.0).collect();
+        ids.sort();
+        assert_eq!(ids, (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_per_shard_order_and_parallelism() {
+        let log = Mutex::new(Vec::new());
+        let observers = observers(40, &log);
+        let mut subject = ShardedSubject::with_shards(4);
+        for observer in &observers {
+            subject.attach(observer);
+        }
+
+        subject.notify_observers();
+
+        let log = log.into_inner().unwrap();
+        for shard in 0..4 {
+            // Round-robin placement puts observer `id` on shard `id % 4`.
+            let seen: Vec<_> = log
+                .iter()
+                .map(|e| e.0)
+                .filter(|id| id % 4 == shard)
+                .collect();
+            let attached: Vec<_> = (shard..40).step_by(4).collect();
+            assert_eq!(seen, attached, "shard {shard} out of order");
+        }
+        let threads: HashSet<_> = log.iter().map(|e| e.1).collect();
+        assert_eq!(threads.len(), 4, "each shard should get its own thread");
+    }
+
+    #[test]
+    fn test_detach() {
+        let log = Mutex::new(Vec::new());
+        let observers = observers(3, &log);
+        let mut subject = ShardedSubject::with_shards(2);
+        for observer in &observers {
+            subject.attach(observer);
+        }
+
+        subject.detach(&observers[1]);
+        subject.notify_observers();
+
+        let mut ids: Vec<_> = log.lock().unwrap().iter().map(|e| e.0).collect();
+        ids.sort();
+        assert_eq!(ids, vec![0, 2]);
+        assert_eq!(subject.shard_sizes(), vec![2, 0]);
+    }
+
+    #[test]
+    fn test_attach_refills_emptiest_shard() {
+        let log = Mutex::new(Vec::new());
+        let observers = observers(12, &log);
+        let mut subject = ShardedSubject::with_shards(4);
+        for observer in &observers[..8] {
+            subject.attach(observer);
+        }
+
+        // Empty shard 1 (observers 1 and 5), then attach four more.
+        subject.detach(&observers[1]);
+        subject.detach(&observers[5]);
+        for observer in &observers[8..] {
+            subject.attach(observer);
+        }
+
+        assert_eq!(subject.shard_sizes(), vec![3, 3, 2, 2]);
+    }
+
+    #[test]
+    fn test_notify_empty_subject() {
+        let subject: ShardedSubject<'_, LogObserver<'_>> =
+            ShardedSubject::with_shards(3);
+
+        assert!(subject.is_empty());
+        subject.notify_observers();
+    }
+}
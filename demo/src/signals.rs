@@ -0,0 +1,603 @@
+//! Glitch-free derived signals.
+//!
+//! Chaining subjects by hand to derive values recomputes a value once per
+//! input that changed, and observers can briefly see a mix of old and new
+//! inputs. This module tracks dependencies instead:
+//!
+//! - A [`Signal`] holds a value that is set from outside.
+//! - A [`Computed`] derives its value from a closure. Every signal or computed
+//!   read inside the closure becomes a dependency automatically and the set is
+//!   re-recorded on each evaluation, so branches are tracked precisely.
+//! - An [`Effect`] runs a closure for its side effects whenever something it
+//!   read changes; it is the observer end of the graph.
+//!
+//! Each node has a height one above its highest dependency. After a change
+//! set (one `set`, or everything inside [`batch`]), stale nodes are
+//! re-evaluated in height order, so every node runs at most once and only
+//! after all of its inputs are final. A computed whose value comes out equal
+//! to the previous one does not wake its dependents.
+//!
+//! Dependents are held weakly. Once the last handle to a [`Computed`] or
+//! [`Effect`] is dropped and nothing depends on it, it is unlinked from the
+//! graph and never evaluated again.
+//!
+//! The graph is per thread; handles are neither `Send` nor `Sync`.
+
+use std::cell::Cell;
+use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::rc::Rc;
+use std::rc::Weak;
+
+trait Node {
+    fn core(&self) -> &NodeCore;
+    /// Re-evaluates the node, returning whether its value changed.
+    fn recompute(&self) -> bool;
+}
+
+struct NodeCore {
+    id: u64,
+    height: Cell<usize>,
+    dependents: RefCell<Vec<Weak<dyn Node>>>,
+}
+
+impl NodeCore {
+    fn new() -> NodeCore {
+        let id = RUNTIME.with(|rt| {
+            let id = rt.next_id.get();
+            rt.next_id.set(id + 1);
+            id
+        });
+        NodeCore {
+            id,
+            height: Cell::new(0),
+            dependents: RefCell::new(Vec::new()),
+        }
+    }
+
+    fn live_dependents(&self) -> Vec<Rc<dyn Node>> {
+        let mut dependents = self.dependents.borrow_mut();
+        dependents.retain(|node| node.strong_count() > 0);
+        dependents.iter().filter_map(Weak::upgrade).collect()
+    }
+
+    fn remove_dependent(&self, id: u64) {
+        self.dependents.borrow_mut().retain(|node| {
+            node.upgrade().is_some_and(|node| node.core().id != id)
+        });
+    }
+
+    // Heights only grow; keeping every dependent strictly above its inputs
+    // is all the scheduler needs.
+    fn raise_height(&self, height: usize) {
+        if height <= self.height.get() {
+            return;
+        }
+        self.height.set(height);
+        for dependent in self.live_dependents() {
+            dependent.core().raise_height(height + 1);
+        }
+    }
+}
+
+struct Runtime {
+    next_id:     Cell<u64>,
+    // Dependencies read by each evaluation in progress, innermost last.
+    tracking:    RefCell<Vec<Vec<Rc<dyn Node>>>>,
+    batch_depth: Cell<usize>,
+    propagating: Cell<bool>,
+    changed:     RefCell<Vec<Rc<dyn Node>>>,
+}
+
+thread_local! {
+    static RUNTIME: Runtime = Runtime {
+        next_id:     Cell::new(0),
+        tracking:    RefCell::new(Vec::new()),
+        batch_depth: Cell::new(0),
+        propagating: Cell::new(false),
+        changed:     RefCell::new(Vec::new()),
+    };
+}
+
+fn track(node: Rc<dyn Node>) {
+    RUNTIME.with(|rt| {
+        if let Some(frame) = rt.tracking.borrow_mut().last_mut() {
+            let id = node.core().id;
+            if !frame.iter().any(|dep| dep.core().id == id) {
+                frame.push(node);
+            }
+        }
+    });
+}
+
+// Collects the nodes read while it is alive. Popped on drop so a panicking
+// closure does not leave a stray frame behind.
+struct TrackingFrame;
+
+impl TrackingFrame {
+    fn push() -> TrackingFrame {
+        RUNTIME.with(|rt| rt.tracking.borrow_mut().push(Vec::new()));
+        TrackingFrame
+    }
+
+    fn finish(self) -> Vec<Rc<dyn Node>> {
+        let deps = RUNTIME.with(|rt| rt.tracking.borrow_mut().pop());
+        std::mem::forget(self);
+        deps.unwrap_or_default()
+    }
+}
+
+impl Drop for TrackingFrame {
+    fn drop(&mut self) {
+        RUNTIME.with(|rt| rt.tracking.borrow_mut().pop());
+    }
+}
+
+// Restores a runtime flag or counter when dropped.
+struct Restore<'c, T: Copy>(&'c Cell<T>, T);
+
+impl<T: Copy> Drop for Restore<'_, T> {
+    fn drop(&mut self) {
+        self.0.set(self.1);
+    }
+}
+
+fn mark_changed(node: Rc<dyn Node>) {
+    let idle = RUNTIME.with(|rt| {
+        rt.changed.borrow_mut().push(node);
+        rt.batch_depth.get() == 0 && !rt.propagating.get()
+    });
+    if idle {
+        flush();
+    }
+}
+
+fn flush() {
+    RUNTIME.with(|rt| {
+        rt.propagating.set(true);
+        let _reset = Restore(&rt.propagating, false);
+        // Signals set by effects form follow-up change sets.
+        loop {
+            let changed = rt.changed.take();
+            if changed.is_empty() {
+                break;
+            }
+            propagate(changed);
+        }
+    });
+}
+
+type Queue = BinaryHeap<Reverse<(usize, u64)>>;
+
+fn propagate(changed: Vec<Rc<dyn Node>>) {
+    let mut heap = Queue::new();
+    // Every node queued during this change set. Entries stay after running
+    // so a node is evaluated at most once.
+    let mut scheduled = HashMap::new();
+
+    for node in &changed {
+        schedule_dependents(node, &mut heap, &mut scheduled);
+    }
+    while let Some(Reverse((height, id))) = heap.pop() {
+        let node = Rc::clone(&scheduled[&id]);
+        // A dependency grew taller since this entry was queued; come back
+        // once everything below the new height has run.
+        let current = node.core().height.get();
+        if current != height {
+            heap.push(Reverse((current, id)));
+            continue;
+        }
+        if node.recompute() {
+            schedule_dependents(&node, &mut heap, &mut scheduled);
+        }
+    }
+}
+
+fn schedule_dependents(
+    node: &Rc<dyn Node>,
+    heap: &mut Queue,
+    scheduled: &mut HashMap<u64, Rc<dyn Node>>,
+) {
+    for dependent in node.core().live_dependents() {
+        let core = dependent.core();
+        let key = (core.height.get(), core.id);
+        if let Entry::Vacant(slot) = scheduled.entry(core.id) {
+            heap.push(Reverse(key));
+            slot.insert(dependent);
+        }
+    }
+}
+
+/// Runs `f` as one change set: dependents see all of its `set` calls at
+/// once, after it returns. Computed values read inside `f` still reflect the
+/// previous change set.
+pub fn batch<R>(f: impl FnOnce() -> R) -> R {
+    let result = RUNTIME.with(|rt| {
+        let depth = rt.batch_depth.get();
+        rt.batch_depth.set(depth + 1);
+        let _reset = Restore(&rt.batch_depth, depth);
+        f()
+    });
+    let idle =
+        RUNTIME.with(|rt| rt.batch_depth.get() == 0 && !rt.propagating.get());
+    if idle {
+        flush();
+    }
+    result
+}
+
+struct SourceNode<T> {
+    core:  NodeCore,
+    value: RefCell<T>,
+}
+
+impl<T> Node for SourceNode<T> {
+    fn core(&self) -> &NodeCore {
+        &self.core
+    }
+
+    fn recompute(&self) -> bool {
+        // Sources have no inputs and are never scheduled.
+        false
+    }
+}
+
+/// Settable root of the dependency graph. Clones share the same value.
+pub struct Signal<T> {
+    node: Rc<SourceNode<T>>,
+}
+
+impl<T: Clone + PartialEq + 'static> Signal<T> {
+    pub fn new(value: T) -> Signal<T> {
+        Signal {
+            node: Rc::new(SourceNode {
+                core:  NodeCore::new(),
+                value: RefCell::new(value),
+            }),
+        }
+    }
+
+    /// Returns the current value, registering a dependency when called from
+    /// a computed or effect.
+    pub fn get(&self) -> T {
+        track(Rc::clone(&self.node) as Rc<dyn Node>);
+        self.node.value.borrow().clone()
+    }
+
+    pub fn set(&self, value: T) {
+        {
+            let mut current = self.node.value.borrow_mut();
+            if *current == value {
+                return;
+            }
+            *current = value;
+        }
+        mark_changed(Rc::clone(&self.node) as Rc<dyn Node>);
+    }
+
+    pub fn update(&self, f: impl FnOnce(&mut T)) {
+        let mut value = self.node.value.borrow().clone();
+        f(&mut value);
+        self.set(value);
+    }
+
+    /// Number of live computations reading this signal.
+    pub fn dependent_count(&self) -> usize {
+        self.node.core.live_dependents().len()
+    }
+}
+
+impl<T> Clone for Signal<T> {
+    fn clone(&self) -> Self {
+        Signal {
+            node: Rc::clone(&self.node),
+        }
+    }
+}
+
+struct ComputedNode<T> {
+    core:    NodeCore,
+    value:   RefCell<Option<T>>,
+    compute: Box<dyn Fn() -> T>,
+    sources: RefCell<Vec<Rc<dyn Node>>>,
+    this:    Weak<dyn Node>,
+}
+
+impl<T: PartialEq + 'static> ComputedNode<T> {
+    fn create(compute: Box<dyn Fn() -> T>) -> Rc<ComputedNode<T>> {
+        let node = Rc::new_cyclic(|this: &Weak<ComputedNode<T>>| {
+            ComputedNode {
+                core: NodeCore::new(),
+                value: RefCell::new(None),
+                compute,
+                sources: RefCell::new(Vec::new()),
+                this: this.clone(),
+            }
+        });
+        node.recompute();
+        node
+    }
+}
+
+impl<T: PartialEq + 'static> Node for ComputedNode<T> {
+    fn core(&self) -> &NodeCore {
+        &self.core
+    }
+
+    fn recompute(&self) -> bool {
+        let frame = TrackingFrame::push();
+        let value = (self.compute)();
+        let sources = frame.finish();
+
+        let has = |nodes: &[Rc<dyn Node>], id| {
+            nodes.iter().any(|n: &Rc<dyn Node>| n.core().id == id)
+        };
+        let old = self.sources.replace(sources);
+        let sources = self.sources.borrow();
+        for source in old.iter() {
+            if !has(&sources, source.core().id) {
+                source.core().remove_dependent(self.core.id);
+            }
+        }
+        for source in sources.iter() {
+            if !has(&old, source.core().id) {
+                source
+                    .core()
+                    .dependents
+                    .borrow_mut()
+                    .push(self.this.clone());
+            }
+        }
+        let height = sources
+            .iter()
+            .map(|source| source.core().height.get() + 1)
+            .max()
+            .unwrap_or(0);
+        self.core.raise_height(height);
+
+        let mut slot = self.value.borrow_mut();
+        let changed = slot.as_ref() != Some(&value);
+        *slot = Some(value);
+        changed
+    }
+}
+
+impl<T> Drop for ComputedNode<T> {
+    fn drop(&mut self) {
+        for source in self.sources.get_mut().iter() {
+            source.core().remove_dependent(self.core.id);
+        }
+    }
+}
+
+/// Value derived from other signals. Clones share the same node.
+pub struct Computed<T> {
+    node: Rc<ComputedNode<T>>,
+}
+
+impl<T: Clone + PartialEq + 'static> Computed<T> {
+    /// Evaluates `compute` immediately to discover its dependencies.
+    pub fn new(compute: impl Fn() -> T + 'static) -> Computed<T> {
+        Computed {
+            node: ComputedNode::create(Box::new(compute)),
+        }
+    }
+
+    pub fn get(&self) -> T {
+        track(Rc::clone(&self.node) as Rc<dyn Node>);
+        self.node
+            .value
+            .borrow()
+            .clone()
+            .expect("computed is evaluated on creation")
+    }
+}
+
+impl<T> Clone for Computed<T> {
+    fn clone(&self) -> Self {
+        Computed {
+            node: Rc::clone(&self.node),
+        }
+    }
+}
+
+/// Side-effecting observer of the graph. Runs once on creation and again
+/// after every change set that touches what it read. Dropping the handle
+/// disposes of it.
+pub struct Effect {
+    _node: Rc<ComputedNode<()>>,
+}
+
+impl Effect {
+    pub fn new(effect: impl Fn() + 'static) -> Effect {
+        Effect {
+            _node: ComputedNode::create(Box::new(effect)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counter() -> (Rc<Cell<u32>>, Rc<Cell<u32>>) {
+        let count = Rc::new(Cell::new(0));
+        (Rc::clone(&count), count)
+    }
+
+    #[test]
+    fn test_computed_follows_sources() {
+        let a = Signal::new(1);
+        let b = Signal::new(2);
+        let sum = Computed::new({
+            let (a, b) = (a.clone(), b.clone());
+            move || a.get() + b.get()
+        });
+
+        assert_eq!(sum.get(), 3);
+        a.set(10);
+        assert_eq!(sum.get(), 12);
+        b.update(|v| *v += 1);
+        assert_eq!(sum.get(), 13);
+    }
+
+    #[test]
+    fn test_diamond_is_glitch_free() {
+        let a = Signal::new(1);
+        let double = Computed::new({
+            let a = a.clone();
+            move || a.get() * 2
+        });
+        let next = Computed::new({
+            let a = a.clone();
+            move || a.get() + 1
+        });
+        let (evaluations, count) = counter();
+        let total = Computed::new({
+            let (double, next) = (double.clone(), next.clone());
+            move || {
+                count.set(count.get() + 1);
+                double.get() + next.get()
+            }
+        });
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let _effect = Effect::new({
+            let (total, seen) = (total.clone(), Rc::clone(&seen));
+            move || seen.borrow_mut().push(total.get())
+        });
+
+        a.set(5);
+
+        // One evaluation at creation, one for the change; never a mix of
+        // old `double` and new `next`.
+        assert_eq!(evaluations.get(), 2);
+        assert_eq!(*seen.borrow(), vec![4, 16]);
+    }
+
+    #[test]
+    fn test_batch_is_one_change_set() {
+        let a = Signal::new(1);
+        let b = Signal::new(1);
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let _effect = Effect::new({
+            let (a, b, seen) = (a.clone(), b.clone(), Rc::clone(&seen));
+            move || seen.borrow_mut().push((a.get(), b.get()))
+        });
+
+        batch(|| {
+            a.set(2);
+            b.set(3);
+            batch(|| a.set(4));
+        });
+
+        assert_eq!(*seen.borrow(), vec![(1, 1), (4, 3)]);
+    }
+
+    #[test]
+    fn test_unchanged_value_stops_propagation() {
+        let a = Signal::new(2);
+        let even = Computed::new({
+            let a = a.clone();
+            move || a.get() % 2 == 0
+        });
+        let (runs, count) = counter();
+        let _effect = Effect::new({
+            let even = even.clone();
+            move || {
+                even.get();
+                count.set(count.get() + 1);
+            }
+        });
+
+        a.set(4);
+
+        assert_eq!(runs.get(), 1);
+    }
+
+    #[test]
+    fn test_dynamic_dependencies() {
+        let use_a = Signal::new(true);
+        let a = Signal::new(1);
+        let b = Signal::new(2);
+        let (evaluations, count) = counter();
+        let pick = Computed::new({
+            let (use_a, a, b) = (use_a.clone(), a.clone(), b.clone());
+            move || {
+                count.set(count.get() + 1);
+                if use_a.get() { a.get() } else { b.get() }
+            }
+        });
+
+        b.set(20);
+        assert_eq!(evaluations.get(), 1);
+        assert_eq!(b.dependent_count(), 0);
+
+        use_a.set(false);
+        assert_eq!(pick.get(), 20);
+        assert_eq!(a.dependent_count(), 0);
+        assert_eq!(b.dependent_count(), 1);
+    }
+
+    #[test]
+    fn test_dropped_computation_is_disposed() {
+        let a = Signal::new(1);
+        let (evaluations, count) = counter();
+        let doubled = Computed::new({
+            let a = a.clone();
+            move || {
+                count.set(count.get() + 1);
+                a.get() * 2
+            }
+        });
+        assert_eq!(a.dependent_count(), 1);
+
+        drop(doubled);
+        a.set(2);
+
+        assert_eq!(evaluations.get(), 1);
+        assert_eq!(a.dependent_count(), 0);
+    }
+
+    #[test]
+    fn test_effect_keeps_dependencies_alive() {
+        let a = Signal::new(1);
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let effect = {
+            let doubled = Computed::new({
+                let a = a.clone();
+                move || a.get() * 2
+            });
+            let seen = Rc::clone(&seen);
+            Effect::new(move || seen.borrow_mut().push(doubled.get()))
+        };
+
+        a.set(3);
+        drop(effect);
+        a.set(4);
+
+        assert_eq!(*seen.borrow(), vec![2, 6]);
+        assert_eq!(a.dependent_count(), 0);
+    }
+
+    #[test]
+    fn test_effect_setting_signal_runs_follow_up() {
+        let a = Signal::new(1);
+        let mirror = Signal::new(0);
+        let _copy = Effect::new({
+            let (a, mirror) = (a.clone(), mirror.clone());
+            move || mirror.set(a.get())
+        });
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let _watch = Effect::new({
+            let (mirror, seen) = (mirror.clone(), Rc::clone(&seen));
+            move || seen.borrow_mut().push(mirror.get())
+        });
+
+        a.set(7);
+
+        assert_eq!(*seen.borrow(), vec![1, 7]);
+    }
+}
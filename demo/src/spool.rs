@@ -0,0 +1,388 @@
+//! Disk-backed queue between a subject and a consumer that may be slow or
+//! unavailable.
+//!
+//! A [`Spool`] is attached to a subject like any observer and writes each
+//! event to a [`Journal`] instead of handing it on. The consumer pulls events
+//! with [`poll`](Spool::poll) whenever it is ready and acknowledges them with
+//! [`ack`](Spool::ack) once they are processed. Acknowledgements are
+//! persisted, and segments that only hold acknowledged events are deleted.
+//!
+//! Delivery is at least once: after a restart, or a
+//! [`rewind`](Spool::rewind), every event that was not acknowledged is
+//! delivered again, including ones the consumer had already seen.
+
+use std::cell::Cell;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::ops::ControlFlow;
+use std::path::PathBuf;
+
+use crate::codec::BinaryCodec;
+use crate::codec::EventCodec;
+use crate::codec::crc32;
+use crate::event::IEventObserver;
+use crate::journal::Journal;
+use crate::journal::JournalOptions;
+use crate::journal::sync_dir;
+
+const ACK_FILE: &str = "ack";
+/// Events [`drain`](Spool::drain) holds in memory and acknowledges at once.
+const DRAIN_BATCH: usize = 256;
+
+pub struct Spool<E, C = BinaryCodec> {
+    journal: Journal<E, C>,
+    dir: PathBuf,
+    /// Every event numbered below this has been acknowledged.
+    acked: Cell<u64>,
+    /// Next event [`poll`](Spool::poll) hands out.
+    delivered: Cell<u64>,
+}
+
+impl<E> Spool<E>
+where
+    BinaryCodec: EventCodec<E>,
+{
+    /// Opens the spool in `dir`, picking up whatever a previous process left
+    /// unacknowledged.
+    pub fn open(
+        dir: impl Into<PathBuf>,
+        options: JournalOptions,
+    ) -> io::Result<Spool<E>> {
+        Spool::with_codec(dir, options, BinaryCodec)
+    }
+}
+
+impl<E, C: EventCodec<E>> Spool<E, C> {
+    pub fn with_codec(
+        dir: impl Into<PathBuf>,
+        options: JournalOptions,
+        codec: C,
+    ) -> io::Result<Spool<E, C>> {
+        let dir = dir.into();
+        let journal = Journal::with_codec(&dir, options, codec)?;
+        let acked = match fs::read(dir.join(ACK_FILE)) {
+            Ok(bytes) => decode_ack(&bytes)?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => 0,
+            Err(err) => return Err(err),
+        };
+        // Events past the end of the journal were lost in a crash along with
+        // their acknowledgement.
+        let acked = acked.min(journal.next_sequence());
+        Ok(Spool {
+            journal,
+            dir,
+            acked: Cell::new(acked),
+            delivered: Cell::new(acked),
+        })
+    }
+
+    /// Persists `event` and returns its sequence number.
+    pub fn enqueue(&self, event: &E) -> io::Result<u64> {
+        self.journal.append(event)
+    }
+
+    /// Returns up to `max` events that have not been handed out yet, oldest
+    /// first, with their sequence numbers. Only the segments holding them are
+    /// read.
+    pub fn poll(&self, max: usize) -> io::Result<Vec<(u64, E)>> {
+        let mut batch = Vec::new();
+        if max == 0 {
+            return Ok(batch);
+        }
+        self.journal
+            .replay_from(self.delivered.get(), |seq, event| {
+                batch.push((seq, event));
+                if batch.len() < max {
+                    ControlFlow::Continue(())
+                } else {
+                    ControlFlow::Break(())
+                }
+            })?;
+        if let Some((seq, _)) = batch.last() {
+            self.delivered.set(seq + 1);
+        }
+        Ok(batch)
+    }
+
+    /// Acknowledges every event up to and including `seq`.
+    pub fn ack(&self, seq: u64) -> io::Result<()> {
+        if seq >= self.delivered.get() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("event {seq} has not been delivered"),
+            ));
+        }
+        if seq < self.acked.get() {
+            return Ok(());
+        }
+        // Write-then-rename, so the file always holds a complete value.
+        let tmp = self.dir.join("ack.tmp");
+        let mut file = File::create(&tmp)?;
+        file.write_all(&encode_ack(seq + 1))?;
+        file.sync_all()?;
+        fs::rename(&tmp, self.dir.join(ACK_FILE))?;
+        sync_dir(&self.dir)?;
+        self.acked.set(seq + 1);
+        self.journal.discard_before(seq + 1)?;
+        Ok(())
+    }
+
+    /// Hands out every unacknowledged event again on the next
+    /// [`poll`](Self::poll), for consumers that lost what they were working
+    /// on.
+    pub fn rewind(&self) {
+        self.delivered.set(self.acked.get());
+    }
+
+    /// Events enqueued but not yet acknowledged.
+    pub fn pending(&self) -> u64 {
+        self.journal.next_sequence() - self.acked.get()
+    }
+
+    /// Delivers every pending event to `observer`, in batches that are
+    /// acknowledged once `update` has returned for each of their events.
+    /// Returns how many were delivered.
+    pub fn drain(
+        &self,
+        observer: &impl IEventObserver<E>,
+    ) -> io::Result<usize> {
+        self.rewind();
+        let mut count = 0;
+        loop {
+            let batch = self.poll(DRAIN_BATCH)?;
+            let Some(&(last, _)) = batch.last() else {
+                return Ok(count);
+            };
+            for (_, event) in &batch {
+                observer.update(event);
+            }
+            self.ack(last)?;
+            count += batch.len();
+        }
+    }
+
+    /// Takes the first error hit while enqueueing as an observer.
+    pub fn take_error(&self) -> Option<io::Error> {
+        self.journal.take_error()
+    }
+
+    pub fn segment_count(&self) -> io::Result<usize> {
+        self.journal.segment_count()
+    }
+}
+
+impl<E, C: EventCodec<E>> IEventObserver<E> for Spool<E, C> {
+    fn update(&self, event: &E) {
+        self.journal.update(event);
+    }
+}
+
+impl<E, C> PartialEq for Spool<E, C> {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+fn encode_ack(next: u64) -> [u8; 12] {
+    let next = next.to_be_bytes();
+    let mut bytes = [0; 12];
+    bytes[..8].copy_from_slice(&next);
+    bytes[8..].copy_from_slice(&crc32(&next).to_be_bytes());
+    bytes
+}
+
+fn decode_ack(bytes: &[u8]) -> io::Result<u64> {
+    let damaged =
+        || io::Error::new(io::ErrorKind::InvalidData, "ack file is damaged");
+    let bytes: [u8; 12] = bytes.try_into().map_err(|_| damaged())?;
+    let (next, checksum) = bytes.split_at(8);
+    if crc32(next).to_be_bytes() != checksum {
+        return Err(damaged());
+    }
+    Ok(u64::from_be_bytes(next.try_into().unwrap()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::path::Path;
+
+    use super::*;
+    use crate::codec::CodecError;
+    use crate::codec::FieldReader;
+    use crate::codec::FieldWriter;
+    use crate::codec::Record;
+    use crate::event::EventSubject;
+    use crate::event::IEventSubject;
+    use crate::journal::SyncPolicy;
+
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> TempDir {
+            let path = std::env::temp_dir()
+                .join(format!("demo-spool-{}-{name}", std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            TempDir(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Job(u64);
+
+    impl Record for Job {
+        const TAG: u32 = 0x4a4f_4221;
+
+        fn write_fields(&self, fields: &mut FieldWriter) {
+            fields.field(1, &self.0);
+        }
+
+        fn read_fields(fields: &FieldReader) -> Result<Self, CodecError> {
+            Ok(Job(fields.get(1)?))
+        }
+    }
+
+    #[derive(Default)]
+    struct Worker {
+        done: RefCell<Vec<u64>>,
+    }
+
+    impl IEventObserver<Job> for Worker {
+        fn update(&self, event: &Job) {
+            self.done.borrow_mut().push(event.0);
+        }
+    }
+
+    fn open(dir: &Path) -> Spool<Job> {
+        let options = JournalOptions {
+            segment_bytes: 64,
+            sync: SyncPolicy::Always,
+        };
+        Spool::open(dir, options).unwrap()
+    }
+
+    fn jobs(batch: &[(u64, Job)]) -> Vec<u64> {
+        batch.iter().map(|(_, job)| job.0).collect()
+    }
+
+    #[test]
+    fn test_buffers_while_consumer_is_away() {
+        let dir = TempDir::new("buffer");
+        let spool = open(&dir.0);
+        let mut subject = EventSubject::new();
+        subject.attach(&spool);
+
+        for n in 0..5 {
+            subject.notify_observers(&Job(n));
+        }
+        assert_eq!(spool.pending(), 5);
+
+        let worker = Worker::default();
+        assert_eq!(spool.drain(&worker).unwrap(), 5);
+        assert_eq!(*worker.done.borrow(), vec![0, 1, 2, 3, 4]);
+        assert_eq!(spool.pending(), 0);
+        assert!(spool.take_error().is_none());
+    }
+
+    #[test]
+    fn test_poll_in_order_and_ack() {
+        let dir = TempDir::new("poll");
+        let spool = open(&dir.0);
+        for n in 0..6 {
+            spool.enqueue(&Job(n)).unwrap();
+        }
+
+        assert_eq!(jobs(&spool.poll(4).unwrap()), vec![0, 1, 2, 3]);
+        assert_eq!(jobs(&spool.poll(4).unwrap()), vec![4, 5]);
+        assert!(spool.poll(4).unwrap().is_empty());
+
+        spool.ack(3).unwrap();
+        assert_eq!(spool.pending(), 2);
+        // Unacknowledged events come back after a rewind.
+        spool.rewind();
+        assert_eq!(jobs(&spool.poll(10).unwrap()), vec![4, 5]);
+    }
+
+    #[test]
+    fn test_resume_after_restart() {
+        let dir = TempDir::new("restart");
+        {
+            let spool = open(&dir.0);
+            for n in 0..5 {
+                spool.enqueue(&Job(n)).unwrap();
+            }
+            spool.poll(3).unwrap();
+            spool.ack(1).unwrap();
+            // Job 2 was handed out but never acknowledged.
+        }
+
+        let spool = open(&dir.0);
+        spool.enqueue(&Job(5)).unwrap();
+
+        assert_eq!(spool.pending(), 4);
+        assert_eq!(jobs(&spool.poll(10).unwrap()), vec![2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_acked_segments_are_deleted() {
+        let dir = TempDir::new("discard");
+        let spool = open(&dir.0);
+        for n in 0..30 {
+            spool.enqueue(&Job(n)).unwrap();
+        }
+        let before = spool.segment_count().unwrap();
+
+        let batch = spool.poll(25).unwrap();
+        spool.ack(batch.last().unwrap().0).unwrap();
+
+        assert!(spool.segment_count().unwrap() < before);
+        assert_eq!(
+            jobs(&spool.poll(10).unwrap()),
+            (25..30).collect::<Vec<_>>()
+        );
+        drop(spool);
+        assert_eq!(open(&dir.0).pending(), 5);
+    }
+
+    #[test]
+    fn test_drain_spans_batches() {
+        let dir = TempDir::new("drain");
+        let spool = open(&dir.0);
+        let total = DRAIN_BATCH as u64 + 10;
+        for n in 0..total {
+            spool.enqueue(&Job(n)).unwrap();
+        }
+
+        let worker = Worker::default();
+        assert_eq!(spool.drain(&worker).unwrap(), total as usize);
+
+        assert_eq!(*worker.done.borrow(), (0..total).collect::<Vec<_>>());
+        assert_eq!(spool.pending(), 0);
+        assert_eq!(spool.segment_count().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_rejects_bad_acks() {
+        let dir = TempDir::new("bad-ack");
+        let spool = open(&dir.0);
+        spool.enqueue(&Job(0)).unwrap();
+
+        let err = spool.ack(0).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        drop(spool);
+        fs::write(dir.0.join(ACK_FILE), b"garbage").unwrap();
+        let err = Spool::<Job>::open(&dir.0, JournalOptions::default())
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,178 @@
+//! Subject whose observer set is fixed at compile time.
+//!
+//! [`Subject`](crate::observer::Subject) keeps a `Vec<&T>`, which costs an
+//! allocation and an indirection per observer and limits it to one observer
+//! type. [`StaticSubject`] instead owns a tuple (or array) of observers.
+//! Tuples, arrays and references of observers are observers themselves, so
+//! `notify_observers` expands into one direct `update` call per element: no
+//! allocation, no virtual dispatch, and every call is visible to the inliner.
+//!
+//! ```
+//! use demo::observer::IObserver;
+//! use demo::static_subject;
+//!
+//! struct Log(&'static str);
+//! impl IObserver for Log {
+//!     fn update(&self) {
+//!         println!("{}", self.0);
+//!     }
+//! }
+//!
+//! let (a, b) = (Log("a"), Log("b"));
+//! let subject = static_subject![&a, &b].attach(Log("c"));
+//! subject.notify_observers(); // prints a, b, c
+//! ```
+
+use crate::observer::IObserver;
+
+/// Statically composed subject. Observers are notified in the order they
+/// appear in `O`.
+pub struct StaticSubject<O: IObserver> {
+    observers: O,
+}
+
+impl StaticSubject<()> {
+    /// Creates a subject with no observers; grow it with
+    /// [`attach`](StaticSubject::attach).
+    pub fn empty() -> StaticSubject<()> {
+        StaticSubject { observers: () }
+    }
+}
+
+impl<O: IObserver> StaticSubject<O> {
+    pub fn new(observers: O) -> StaticSubject<O> {
+        StaticSubject { observers }
+    }
+
+    /// Returns a subject that notifies `observer` after the current set.
+    ///
+    /// Unlike [`ISubject::attach`](crate::observer::ISubject::attach) this
+    /// changes the subject's type, so the full set stays known at compile
+    /// time. There is no `detach` for the same reason.
+    pub fn attach<N: IObserver>(self, observer: N) -> StaticSubject<(O, N)> {
+        StaticSubject {
+            observers: (self.observers, observer),
+        }
+    }
+
+    #[inline]
+    pub fn notify_observers(&self) {
+        self.observers.update();
+    }
+
+    pub fn observers(&self) -> &O {
+        &self.observers
+    }
+
+    pub fn into_observers(self) -> O {
+        self.observers
+    }
+}
+
+/// Builds a [`StaticSubject`] from a list of observers.
+#[macro_export]
+macro_rules! static_subject {
+    ($($observer:expr),* $(,)?) => {
+        $crate::static_subject::StaticSubject::new(($($observer,)*))
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::mem::size_of;
+
+    use super::*;
+
+    // Appends its id to a shared log when updated.
+    struct LogObserver<'l> {
+        id:  i32,
+        log: &'l RefCell<Vec<i32>>,
+    }
+
+    impl IObserver for LogObserver<'_> {
+        fn update(&self) {
+            self.log.borrow_mut().push(self.id);
+        }
+    }
+
+    // A second observer type, to show tuples may mix types.
+    struct CountObserver {
+        count: RefCell<u32>,
+    }
+
+    impl IObserver for CountObserver {
+        fn update(&self) {
+            *self.count.borrow_mut() += 1;
+        }
+    }
+
+    #[test]
+    fn test_tuple_notifies_in_order() {
+        let log = RefCell::new(Vec::new());
+        let a = LogObserver { id: 1, log: &log };
+        let b = LogObserver { id: 2, log: &log };
+        let counter = CountObserver {
+            count: RefCell::new(0),
+        };
+
+        let subject = StaticSubject::new((&a, &counter, &b));
+        subject.notify_observers();
+        subject.notify_observers();
+
+        assert_eq!(*log.borrow(), vec![1, 2, 1, 2]);
+        assert_eq!(*counter.count.borrow(), 2);
+    }
+
+    #[test]
+    fn test_attach_appends() {
+        let log = RefCell::new(Vec::new());
+
+        let subject = StaticSubject::empty()
+            .attach(LogObserver { id: 1, log: &log })
+            .attach(LogObserver { id: 2, log: &log })
+            .attach(LogObserver { id: 3, log: &log });
+        subject.notify_observers();
+
+        assert_eq!(*log.borrow(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_array_observers() {
+        let log = RefCell::new(Vec::new());
+        let observers: [LogObserver<'_>; 3] = std::array::from_fn(|i| {
+            LogObserver {
+                id:  i as i32,
+                log: &log,
+            }
+        });
+
+        StaticSubject::new(observers).notify_observers();
+
+        assert_eq!(*log.borrow(), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_macro() {
+        let log = RefCell::new(Vec::new());
+        let a = LogObserver { id: 1, log: &log };
+        let b = LogObserver { id: 2, log: &log };
+
+        let subject = static_subject![&a, &b,];
+        subject.notify_observers();
+
+        assert_eq!(*log.borrow(), vec![1, 2]);
+        static_subject![].notify_observers();
+    }
+
+    #[test]
+    fn test_no_hidden_storage() {
+        // The subject is exactly as large as the references it holds.
+        type Pair<'l> = (&'l LogObserver<'l>, &'l CountObserver);
+        assert_eq!(
+            size_of::<StaticSubject<Pair<'_>>>(),
+            2 * size_of::<usize>()
+        );
+        assert_eq!(size_of::<StaticSubject<()>>(), 0);
+    }
+}
@@ -0,0 +1,285 @@
+//! Adapters that thin out a stream of events before it reaches an observer.
+//!
+//! Both wrap a target observer and are observers themselves, so they can be
+//! attached to a subject in its place:
+//!
+//! - [`RateLimiter`] forwards at most a fixed rate, with room for bursts, and
+//!   drops the rest.
+//! - [`Sampler`] forwards every Nth event, or each event with a fixed
+//!   probability.
+//!
+//! Time is read through a [`Clock`], so tests can use a
+//! [`ManualClock`](crate::clock::ManualClock).
+
+use std::cell::Cell;
+use std::time::Duration;
+
+use crate::clock::Clock;
+use crate::clock::SystemClock;
+use crate::event::IEventObserver;
+use crate::rng::Rng;
+
+/// Token bucket: holds up to `burst` tokens, gains `rate` tokens per second,
+/// and each forwarded event spends one.
+pub struct RateLimiter<'a, O: ?Sized, C = SystemClock> {
+    target:    &'a O,
+    rate:      f64,
+    burst:     f64,
+    clock:     C,
+    tokens:    Cell<f64>,
+    refilled:  Cell<Duration>,
+    forwarded: Cell<u64>,
+    dropped:   Cell<u64>,
+}
+
+impl<'a, O: ?Sized> RateLimiter<'a, O> {
+    pub fn new(target: &'a O, rate: f64, burst: u32) -> RateLimiter<'a, O> {
+        RateLimiter::with_clock(target, rate, burst, SystemClock::new())
+    }
+}
+
+impl<'a, O: ?Sized, C: Clock> RateLimiter<'a, O, C> {
+    /// Starts with a full bucket.
+    pub fn with_clock(
+        target: &'a O,
+        rate: f64,
+        burst: u32,
+        clock: C,
+    ) -> RateLimiter<'a, O, C> {
+        let burst = f64::from(burst.max(1));
+        RateLimiter {
+            target,
+            rate,
+            burst,
+            refilled: Cell::new(clock.now()),
+            clock,
+            tokens: Cell::new(burst),
+            forwarded: Cell::new(0),
+            dropped: Cell::new(0),
+        }
+    }
+
+    pub fn forwarded(&self) -> u64 {
+        self.forwarded.get()
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.dropped.get()
+    }
+
+    fn take_token(&self) -> bool {
+        let now = self.clock.now();
+        let elapsed = now.saturating_sub(self.refilled.get());
+        self.refilled.set(now);
+        let tokens = (self.tokens.get() + elapsed.as_secs_f64() * self.rate)
+            .min(self.burst);
+        if tokens >= 1.0 {
+            self.tokens.set(tokens - 1.0);
+            true
+        } else {
+            self.tokens.set(tokens);
+            false
+        }
+    }
+}
+
+impl<E, O, C> IEventObserver<E> for RateLimiter<'_, O, C>
+where
+    O: IEventObserver<E> + ?Sized,
+    C: Clock,
+{
+    fn update(&self, event: &E) {
+        if self.take_token() {
+            self.forwarded.set(self.forwarded.get() + 1);
+            self.target.update(event);
+        } else {
+            self.dropped.set(self.dropped.get() + 1);
+        }
+    }
+}
+
+impl<O: ?Sized, C> PartialEq for RateLimiter<'_, O, C> {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+enum Sampling {
+    EveryNth { n: u64, seen: Cell<u64> },
+    Fraction { p: f64, rng: Rng },
+}
+
+pub struct Sampler<'a, O: ?Sized> {
+    target:    &'a O,
+    sampling:  Sampling,
+    forwarded: Cell<u64>,
+}
+
+impl<'a, O: ?Sized> Sampler<'a, O> {
+    /// Forwards the 1st, (n+1)th, (2n+1)th, ... event.
+    pub fn every_nth(target: &'a O, n: u64) -> Sampler<'a, O> {
+        Sampler::with(
+            target,
+            Sampling::EveryNth {
+                n:    n.max(1),
+                seen: Cell::new(0),
+            },
+        )
+    }
+
+    /// Forwards each event with probability `p`.
+    pub fn fraction(target: &'a O, p: f64) -> Sampler<'a, O> {
+        Sampler::with(
+            target,
+            Sampling::Fraction {
+                p:   p.clamp(0.0, 1.0),
+                rng: Rng::new(),
+            },
+        )
+    }
+
+    /// Makes a [`fraction`](Self::fraction) sampler reproducible.
+    pub fn with_seed(mut self, seed: u64) -> Sampler<'a, O> {
+        if let Sampling::Fraction { rng, .. } = &mut self.sampling {
+            *rng = Rng::seeded(seed);
+        }
+        self
+    }
+
+    pub fn forwarded(&self) -> u64 {
+        self.forwarded.get()
+    }
+
+    fn with(target: &'a O, sampling: Sampling) -> Sampler<'a, O> {
+        Sampler {
+            target,
+            sampling,
+            forwarded: Cell::new(0),
+        }
+    }
+
+    fn pick(&self) -> bool {
+        match &self.sampling {
+            Sampling::EveryNth { n, seen } => {
+                let count = seen.replace(seen.get() + 1);
+                count % n == 0
+            },
+            Sampling::Fraction { p, rng } => rng.next_f64() < *p,
+        }
+    }
+}
+
+impl<E, O: IEventObserver<E> + ?Sized> IEventObserver<E> for Sampler<'_, O> {
+    fn update(&self, event: &E) {
+        if self.pick() {
+            self.forwarded.set(self.forwarded.get() + 1);
+            self.target.update(event);
+        }
+    }
+}
+
+impl<O: ?Sized> PartialEq for Sampler<'_, O> {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::event::EventSubject;
+    use crate::event::IEventSubject;
+
+    #[derive(Default)]
+    struct Recorder {
+        seen: RefCell<Vec<u32>>,
+    }
+
+    impl IEventObserver<u32> for Recorder {
+        fn update(&self, event: &u32) {
+            self.seen.borrow_mut().push(*event);
+        }
+    }
+
+    #[test]
+    fn test_rate_limiter_allows_burst_then_rate() {
+        let clock = ManualClock::new();
+        let sink = Recorder::default();
+        let limiter = RateLimiter::with_clock(&sink, 10.0, 3, &clock);
+        let mut subject = EventSubject::new();
+        subject.attach(&limiter);
+
+        // A burst of 5 at once: the bucket holds 3.
+        for n in 0..5 {
+            subject.notify_observers(&n);
+        }
+        assert_eq!(*sink.seen.borrow(), vec![0, 1, 2]);
+
+        // 10 per second is one every 100 ms.
+        for n in 5..15 {
+            clock.advance(Duration::from_millis(50));
+            subject.notify_observers(&n);
+        }
+        assert_eq!(*sink.seen.borrow(), vec![0, 1, 2, 6, 8, 10, 12, 14]);
+        assert_eq!(limiter.forwarded(), 8);
+        assert_eq!(limiter.dropped(), 7);
+    }
+
+    #[test]
+    fn test_rate_limiter_bucket_does_not_overfill() {
+        let clock = ManualClock::new();
+        let sink = Recorder::default();
+        let limiter = RateLimiter::with_clock(&sink, 1.0, 2, &clock);
+
+        clock.advance(Duration::from_secs(60));
+        for n in 0..5 {
+            limiter.update(&n);
+        }
+
+        assert_eq!(limiter.forwarded(), 2);
+    }
+
+    #[test]
+    fn test_every_nth() {
+        let sink = Recorder::default();
+        let sampler = Sampler::every_nth(&sink, 3);
+
+        for n in 0..10 {
+            sampler.update(&n);
+        }
+
+        assert_eq!(*sink.seen.borrow(), vec![0, 3, 6, 9]);
+    }
+
+    #[test]
+    fn test_fraction_is_close_to_p() {
+        let sink = Recorder::default();
+        let sampler = Sampler::fraction(&sink, 0.25).with_seed(7);
+
+        for n in 0..10_000 {
+            sampler.update(&n);
+        }
+
+        let forwarded = sampler.forwarded();
+        assert!((2_200..2_800).contains(&forwarded), "{forwarded}");
+        assert_eq!(sink.seen.borrow().len() as u64, forwarded);
+    }
+
+    #[test]
+    fn test_fraction_extremes() {
+        let sink = Recorder::default();
+        let none = Sampler::fraction(&sink, 0.0);
+        let all = Sampler::fraction(&sink, 1.0);
+
+        for n in 0..100 {
+            none.update(&n);
+            all.update(&n);
+        }
+
+        assert_eq!(none.forwarded(), 0);
+        assert_eq!(all.forwarded(), 100);
+    }
+}
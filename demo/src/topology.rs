@@ -0,0 +1,283 @@
+//! Export of how subjects, relays and observers are wired together.
+//!
+//! A [`Topology`] is a small directed graph that is filled in by hand with
+//! [`add`](Topology::add) and [`connect`](Topology::connect), or from a
+//! running [`InspectableSubject`](crate::inspect::InspectableSubject) with
+//! [`add_observers`](Topology::add_observers), and rendered as Graphviz DOT or
+//! Mermaid. Edge labels list the filters an event must pass to cross the
+//! edge and the edge's priority, if it has one.
+//!
+//! Output depends only on the order nodes and edges were added, so it can be
+//! compared against a checked-in snapshot.
+
+use std::fmt::Write;
+
+use crate::inspect::ObserverInfo;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    Subject,
+    /// Both observes and notifies, like a
+    /// [`Processor`](crate::processor::Processor).
+    Relay,
+    Observer,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Node(usize);
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EdgeLabel {
+    pub filters:  Vec<String>,
+    pub priority: Option<i32>,
+}
+
+impl EdgeLabel {
+    fn lines(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        if !self.filters.is_empty() {
+            lines.push(format!("filter: {}", self.filters.join(", ")));
+        }
+        if let Some(priority) = self.priority {
+            lines.push(format!("priority: {priority}"));
+        }
+        lines
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Topology {
+    /// Kind and label lines of each node.
+    nodes: Vec<(NodeKind, Vec<String>)>,
+    edges: Vec<(Node, Node, EdgeLabel)>,
+}
+
+impl Topology {
+    pub fn new() -> Topology {
+        Topology::default()
+    }
+
+    pub fn add(&mut self, kind: NodeKind, label: &str) -> Node {
+        self.nodes.push((kind, vec![label.to_string()]));
+        Node(self.nodes.len() - 1)
+    }
+
+    pub fn connect(&mut self, from: Node, to: Node, label: EdgeLabel) {
+        self.edges.push((from, to, label));
+    }
+
+    /// Adds a node for each observer in `observers`, as returned by
+    /// [`InspectableSubject::observers`](crate::inspect::InspectableSubject::observers),
+    /// labelled with its type and owner and connected from `subject` through
+    /// its filters. Returns the new nodes in the same order.
+    pub fn add_observers(
+        &mut self,
+        subject: Node,
+        observers: &[ObserverInfo],
+    ) -> Vec<Node> {
+        observers
+            .iter()
+            .map(|info| {
+                let mut label = vec![format!(
+                    "{} {}",
+                    short_type_name(info.type_name),
+                    info.id
+                )];
+                if let Some(owner) = &info.owner {
+                    label.push(format!("owner: {owner}"));
+                }
+                self.nodes.push((NodeKind::Observer, label));
+                let node = Node(self.nodes.len() - 1);
+                let edge = EdgeLabel {
+                    filters:  info.filters.clone(),
+                    priority: None,
+                };
+                self.connect(subject, node, edge);
+                node
+            })
+            .collect()
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph observers {\n");
+        for (idx, (kind, label)) in self.nodes.iter().enumerate() {
+            let shape = match kind {
+                NodeKind::Subject => "box",
+                NodeKind::Relay => "hexagon",
+                NodeKind::Observer => "ellipse",
+            };
+            let label = dot_escape(label);
+            let _ =
+                writeln!(out, "    n{idx} [label=\"{label}\", shape={shape}];");
+        }
+        for (from, to, label) in &self.edges {
+            let lines = label.lines();
+            let _ = write!(out, "    n{} -> n{}", from.0, to.0);
+            if !lines.is_empty() {
+                let _ = write!(out, " [label=\"{}\"]", dot_escape(&lines));
+            }
+            out.push_str(";\n");
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    pub fn to_mermaid(&self) -> String {
+        let mut out = String::from("flowchart LR\n");
+        for (idx, (kind, label)) in self.nodes.iter().enumerate() {
+            let label = mermaid_escape(label);
+            let _ = match kind {
+                NodeKind::Subject => writeln!(out, "    n{idx}[\"{label}\"]"),
+                NodeKind::Relay => {
+                    writeln!(out, "    n{idx}{{{{\"{label}\"}}}}")
+                },
+                NodeKind::Observer => {
+                    writeln!(out, "    n{idx}([\"{label}\"])")
+                },
+            };
+        }
+        for (from, to, label) in &self.edges {
+            let lines = label.lines();
+            if lines.is_empty() {
+                let _ = writeln!(out, "    n{} --> n{}", from.0, to.0);
+            } else {
+                let label = mermaid_escape(&lines);
+                let _ =
+                    writeln!(out, "    n{} -->|\"{label}\"| n{}", from.0, to.0);
+            }
+        }
+        out
+    }
+}
+
+fn dot_escape(lines: &[String]) -> String {
+    lines
+        .iter()
+        .map(|line| line.replace('\\', "\\\\").replace('"', "\\\""))
+        .collect::<Vec<_>>()
+        .join("\\n")
+}
+
+fn mermaid_escape(lines: &[String]) -> String {
+    lines
+        .iter()
+        .map(|line| line.replace('"', "#quot;"))
+        .collect::<Vec<_>>()
+        .join("<br/>")
+}
+
+/// Drops module paths from a type name, including inside generics:
+/// `a::b::Outer<c::Inner>` becomes `Outer<Inner>`.
+fn short_type_name(name: &str) -> String {
+    let mut out = String::new();
+    let mut segment = String::new();
+    let mut chars = name.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == ':' && chars.peek() == Some(&':') {
+            chars.next();
+            segment.clear();
+        } else if c.is_alphanumeric() || c == '_' {
+            segment.push(c);
+        } else {
+            out.push_str(&segment);
+            segment.clear();
+            out.push(c);
+        }
+    }
+    out.push_str(&segment);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::event::IEventObserver;
+    use crate::inspect::InspectableSubject;
+
+    struct Sink;
+
+    impl PartialEq for Sink {
+        fn eq(&self, other: &Self) -> bool {
+            std::ptr::eq(self, other)
+        }
+    }
+
+    impl IEventObserver<i32> for Sink {
+        fn update(&self, _event: &i32) {}
+    }
+
+    // orders -> (Sink #0, Sink #1 "billing" behind a filter), and Sink #1
+    // relays to an "audit" subject at priority 10.
+    fn topology() -> Topology {
+        let clock = ManualClock::new();
+        let (first, second) = (Sink, Sink);
+        let mut subject = InspectableSubject::with_clock(&clock);
+        subject.attach(&first);
+        let id = subject.attach(&second);
+        subject.tag(id, "billing");
+        subject.filter(id, "amount > 0", |n: &i32| *n > 0);
+
+        let mut topology = Topology::new();
+        let orders = topology.add(NodeKind::Subject, "orders");
+        let observers = topology.add_observers(orders, &subject.observers());
+        let relay = topology.add(NodeKind::Relay, "to \"audit\"");
+        let audit = topology.add(NodeKind::Subject, "audit");
+        topology.connect(observers[1], relay, EdgeLabel::default());
+        topology.connect(
+            relay,
+            audit,
+            EdgeLabel {
+                filters:  Vec::new(),
+                priority: Some(10),
+            },
+        );
+        topology
+    }
+
+    #[test]
+    fn test_dot_snapshot() {
+        assert_eq!(
+            topology().to_dot(),
+            r#"digraph observers {
+    n0 [label="orders", shape=box];
+    n1 [label="Sink #0", shape=ellipse];
+    n2 [label="Sink #1\nowner: billing", shape=ellipse];
+    n3 [label="to \"audit\"", shape=hexagon];
+    n4 [label="audit", shape=box];
+    n0 -> n1;
+    n0 -> n2 [label="filter: amount > 0"];
+    n2 -> n3;
+    n3 -> n4 [label="priority: 10"];
+}
+"#
+        );
+    }
+
+    #[test]
+    fn test_mermaid_snapshot() {
+        assert_eq!(
+            topology().to_mermaid(),
+            r#"flowchart LR
+    n0["orders"]
+    n1(["Sink #0"])
+    n2(["Sink #1<br/>owner: billing"])
+    n3{{"to #quot;audit#quot;"}}
+    n4["audit"]
+    n0 --> n1
+    n0 -->|"filter: amount > 0"| n2
+    n2 --> n3
+    n3 -->|"priority: 10"| n4
+"#
+        );
+    }
+
+    #[test]
+    fn test_short_type_name() {
+        assert_eq!(
+            short_type_name("a::b::Outer<c::Inner, u8>"),
+            "Outer<Inner, u8>"
+        );
+        assert_eq!(short_type_name("&dyn a::Trait<i32>"), "&dyn Trait<i32>");
+    }
+}
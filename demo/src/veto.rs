@@ -0,0 +1,212 @@
+//! Vetoable, two-phase change notifications.
+//!
+//! [`VetoableSubject::propose`] first asks every observer to
+//! [`prepare`](IVetoableObserver::prepare) for a change. Any observer may
+//! refuse with a [`Veto`]; observers that had already prepared are then sent
+//! [`abort`](IVetoableObserver::abort), most recently prepared first, and the
+//! change is not applied. Only when every observer accepts is the change
+//! applied and [`commit`](IVetoableObserver::commit) sent to all of them.
+
+use std::error::Error;
+use std::fmt;
+use std::marker::PhantomData;
+
+pub trait IVetoableObserver<E> {
+    /// Checks a proposed change. Returning `Err` stops the change.
+    fn prepare(&self, change: &E) -> Result<(), Veto>;
+    /// The change has been applied.
+    fn commit(&self, change: &E);
+    /// A later observer vetoed a change this observer had prepared for.
+    fn abort(&self, _change: &E, _veto: &Veto) {}
+}
+
+/// Reason an observer gave for rejecting a change.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Veto {
+    pub reason: String,
+}
+
+impl Veto {
+    pub fn new(reason: impl Into<String>) -> Veto {
+        Veto {
+            reason: reason.into(),
+        }
+    }
+}
+
+impl fmt::Display for Veto {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "change vetoed: {}", self.reason)
+    }
+}
+
+impl Error for Veto {}
+
+pub struct VetoableSubject<'a, E, T: IVetoableObserver<E>> {
+    observers: Vec<&'a T>,
+    _change:   PhantomData<fn(&E)>,
+}
+
+impl<'a, E, T: IVetoableObserver<E> + PartialEq> VetoableSubject<'a, E, T> {
+    pub fn new() -> VetoableSubject<'a, E, T> {
+        VetoableSubject {
+            observers: Vec::new(),
+            _change:   PhantomData,
+        }
+    }
+
+    pub fn attach(&mut self, observer: &'a T) {
+        self.observers.push(observer);
+    }
+
+    pub fn detach(&mut self, observer: &'a T) {
+        if let Some(idx) = self.observers.iter().position(|x| *x == observer) {
+            self.observers.remove(idx);
+        }
+    }
+
+    /// Runs the two-phase protocol for `change`, calling `apply` between a
+    /// unanimous prepare and the commit notifications.
+    pub fn propose(
+        &self,
+        change: &E,
+        apply: impl FnOnce(&E),
+    ) -> Result<(), Veto> {
+        for (idx, item) in self.observers.iter().enumerate() {
+            if let Err(veto) = item.prepare(change) {
+                for prepared in self.observers[..idx].iter().rev() {
+                    prepared.abort(change, &veto);
+                }
+                return Err(veto);
+            }
+        }
+        apply(change);
+        for item in self.observers.iter() {
+            item.commit(change);
+        }
+        Ok(())
+    }
+}
+
+impl<'a, E, T: IVetoableObserver<E> + PartialEq> Default
+    for VetoableSubject<'a, E, T>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::cell::RefCell;
+
+    use super::*;
+
+    // Accepts changes up to `limit` and logs every callback.
+    #[derive(PartialEq)]
+    struct QuotaObserver<'l> {
+        id:    i32,
+        limit: u32,
+        log:   &'l RefCell<Vec<String>>,
+    }
+
+    impl IVetoableObserver<u32> for QuotaObserver<'_> {
+        fn prepare(&self, change: &u32) -> Result<(), Veto> {
+            self.log.borrow_mut().push(format!("prepare {}", self.id));
+            if *change > self.limit {
+                return Err(Veto::new(format!("{change} over quota")));
+            }
+            Ok(())
+        }
+
+        fn commit(&self, _change: &u32) {
+            self.log.borrow_mut().push(format!("commit {}", self.id));
+        }
+
+        fn abort(&self, _change: &u32, veto: &Veto) {
+            self.log
+                .borrow_mut()
+                .push(format!("abort {}: {}", self.id, veto.reason));
+        }
+    }
+
+    #[test]
+    fn test_unanimous_change_commits() {
+        let log = RefCell::new(Vec::new());
+        let a = QuotaObserver {
+            id:    1,
+            limit: 10,
+            log:   &log,
+        };
+        let b = QuotaObserver {
+            id:    2,
+            limit: 10,
+            log:   &log,
+        };
+        let mut subject = VetoableSubject::new();
+        subject.attach(&a);
+        subject.attach(&b);
+        let applied = Cell::new(false);
+
+        let result = subject.propose(&5, |_| applied.set(true));
+
+        assert_eq!(result, Ok(()));
+        assert!(applied.get());
+        assert_eq!(
+            *log.borrow(),
+            vec!["prepare 1", "prepare 2", "commit 1", "commit 2"]
+        );
+    }
+
+    #[test]
+    fn test_veto_aborts_prepared_observers() {
+        let log = RefCell::new(Vec::new());
+        let observers: Vec<_> = [10, 10, 3, 10]
+            .into_iter()
+            .enumerate()
+            .map(|(id, limit)| {
+                QuotaObserver {
+                    id: id as i32,
+                    limit,
+                    log: &log,
+                }
+            })
+            .collect();
+        let mut subject = VetoableSubject::new();
+        for observer in &observers {
+            subject.attach(observer);
+        }
+        let applied = Cell::new(false);
+
+        let result = subject.propose(&5, |_| applied.set(true));
+
+        assert_eq!(result, Err(Veto::new("5 over quota")));
+        assert!(!applied.get());
+        assert_eq!(
+            *log.borrow(),
+            vec![
+                "prepare 0",
+                "prepare 1",
+                "prepare 2",
+                "abort 1: 5 over quota",
+                "abort 0: 5 over quota",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_first_observer_veto() {
+        let log = RefCell::new(Vec::new());
+        let a = QuotaObserver {
+            id:    1,
+            limit: 0,
+            log:   &log,
+        };
+        let mut subject = VetoableSubject::new();
+        subject.attach(&a);
+
+        assert!(subject.propose(&1, |_| {}).is_err());
+        assert_eq!(*log.borrow(), vec!["prepare 1"]);
+    }
+}
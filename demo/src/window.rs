@@ -0,0 +1,296 @@
+//! Summaries of a numeric event stream over time windows.
+//!
+//! A [`WindowAggregator`] observes events, extracts a number from each, and
+//! when a window closes sends a [`Summary`] (count, sum, min and max) to its
+//! target instead of the events themselves.
+//!
+//! Windows are laid out from the clock's origin:
+//!
+//! - [`Window::Tumbling`] windows follow each other without overlap, so every
+//!   event lands in exactly one.
+//! - [`Window::Sliding`] windows start every `step` and last `size`, so an
+//!   event lands in every window covering its timestamp.
+//!
+//! A window is closed by the first event at or past its end, or by
+//! [`WindowAggregator::tick`] for callers that want summaries while the
+//! stream is quiet. Windows that saw no events are skipped.
+
+use std::cell::Cell;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::clock::Clock;
+use crate::clock::SystemClock;
+use crate::event::IEventObserver;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Window {
+    Tumbling(Duration),
+    Sliding { size: Duration, step: Duration },
+}
+
+impl Window {
+    fn size_and_step(self) -> (u128, u128) {
+        let (size, step) = match self {
+            Window::Tumbling(size) => (size, size),
+            Window::Sliding { size, step } => (size, step),
+        };
+        (size.as_nanos().max(1), step.as_nanos().max(1))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Summary {
+    pub start: Duration,
+    pub end:   Duration,
+    pub count: u64,
+    pub sum:   f64,
+    pub min:   f64,
+    pub max:   f64,
+}
+
+impl Summary {
+    pub fn mean(&self) -> f64 {
+        self.sum / self.count as f64
+    }
+}
+
+pub struct WindowAggregator<'a, E, O: ?Sized, C = SystemClock> {
+    target:  &'a O,
+    value:   Box<dyn Fn(&E) -> f64 + 'a>,
+    size:    u128,
+    step:    u128,
+    clock:   C,
+    samples: RefCell<VecDeque<(u128, f64)>>,
+    /// Index of the next window to close.
+    next:    Cell<u128>,
+}
+
+impl<'a, E, O> WindowAggregator<'a, E, O>
+where
+    O: IEventObserver<Summary> + ?Sized,
+{
+    pub fn new(
+        target: &'a O,
+        window: Window,
+        value: impl Fn(&E) -> f64 + 'a,
+    ) -> WindowAggregator<'a, E, O> {
+        WindowAggregator::with_clock(target, window, value, SystemClock::new())
+    }
+}
+
+impl<'a, E, O, C> WindowAggregator<'a, E, O, C>
+where
+    O: IEventObserver<Summary> + ?Sized,
+    C: Clock,
+{
+    pub fn with_clock(
+        target: &'a O,
+        window: Window,
+        value: impl Fn(&E) -> f64 + 'a,
+        clock: C,
+    ) -> WindowAggregator<'a, E, O, C> {
+        let (size, step) = window.size_and_step();
+        WindowAggregator {
+            target,
+            value: Box::new(value),
+            size,
+            step,
+            clock,
+            samples: RefCell::new(VecDeque::new()),
+            next: Cell::new(0),
+        }
+    }
+
+    /// Sends summaries for every window that has ended by now.
+    pub fn tick(&self) {
+        let now = self.clock.now().as_nanos();
+        loop {
+            let mut samples = self.samples.borrow_mut();
+            // Skip ahead over windows that cannot hold anything.
+            let first_useful = match samples.front() {
+                Some(&(at, _)) => windows_ending_by(at, self.size, self.step),
+                None => windows_ending_by(now, self.size, self.step),
+            };
+            let k = self.next.get().max(first_useful);
+            let start = k * self.step;
+            let end = start + self.size;
+            if end > now {
+                self.next.set(k);
+                return;
+            }
+            let summary = summarize(&samples, start, end);
+            self.next.set(k + 1);
+            // Samples before the next window's start are no longer needed.
+            let keep_from = (k + 1) * self.step;
+            while samples.front().is_some_and(|&(at, _)| at < keep_from) {
+                samples.pop_front();
+            }
+            drop(samples);
+            if let Some(summary) = summary {
+                self.target.update(&summary);
+            }
+        }
+    }
+}
+
+impl<E, O, C> IEventObserver<E> for WindowAggregator<'_, E, O, C>
+where
+    O: IEventObserver<Summary> + ?Sized,
+    C: Clock,
+{
+    fn update(&self, event: &E) {
+        self.tick();
+        let now = self.clock.now().as_nanos();
+        let value = (self.value)(event);
+        self.samples.borrow_mut().push_back((now, value));
+    }
+}
+
+impl<E, O: ?Sized, C> PartialEq for WindowAggregator<'_, E, O, C> {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+/// Number of windows that end at or before `at`, i.e. the index of the first
+/// window still open at `at`.
+fn windows_ending_by(at: u128, size: u128, step: u128) -> u128 {
+    if at < size { 0 } else { (at - size) / step + 1 }
+}
+
+fn summarize(
+    samples: &VecDeque<(u128, f64)>,
+    start: u128,
+    end: u128,
+) -> Option<Summary> {
+    let mut summary: Option<Summary> = None;
+    for &(_, value) in
+        samples.iter().filter(|(at, _)| (start..end).contains(at))
+    {
+        let s = summary.get_or_insert(Summary {
+            start: nanos(start),
+            end:   nanos(end),
+            count: 0,
+            sum:   0.0,
+            min:   value,
+            max:   value,
+        });
+        s.count += 1;
+        s.sum += value;
+        s.min = s.min.min(value);
+        s.max = s.max.max(value);
+    }
+    summary
+}
+
+fn nanos(n: u128) -> Duration {
+    Duration::from_nanos(n as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    #[derive(Default)]
+    struct Collector {
+        summaries: RefCell<Vec<Summary>>,
+    }
+
+    impl IEventObserver<Summary> for Collector {
+        fn update(&self, event: &Summary) {
+            self.summaries.borrow_mut().push(*event);
+        }
+    }
+
+    fn secs(n: u64) -> Duration {
+        Duration::from_secs(n)
+    }
+
+    fn counts(collector: &Collector) -> Vec<(u64, u64, f64)> {
+        collector
+            .summaries
+            .borrow()
+            .iter()
+            .map(|s| (s.start.as_secs(), s.count, s.sum))
+            .collect()
+    }
+
+    #[test]
+    fn test_tumbling_windows() {
+        let clock = ManualClock::new();
+        let sink = Collector::default();
+        let agg = WindowAggregator::with_clock(
+            &sink,
+            Window::Tumbling(secs(10)),
+            |n: &i32| f64::from(*n),
+            &clock,
+        );
+
+        for n in [3, 7, -2] {
+            agg.update(&n);
+            clock.advance(secs(4));
+        }
+        // Now at 12 s: the first window closes with this event.
+        agg.update(&100);
+
+        let summaries = sink.summaries.borrow();
+        assert_eq!(summaries.len(), 1);
+        let first = summaries[0];
+        assert_eq!((first.start, first.end), (secs(0), secs(10)));
+        assert_eq!((first.count, first.sum), (3, 8.0));
+        assert_eq!((first.min, first.max), (-2.0, 7.0));
+        assert!((first.mean() - 8.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_tick_closes_windows_and_skips_empty_ones() {
+        let clock = ManualClock::new();
+        let sink = Collector::default();
+        let agg = WindowAggregator::with_clock(
+            &sink,
+            Window::Tumbling(secs(1)),
+            |n: &u32| f64::from(*n),
+            &clock,
+        );
+
+        agg.update(&1);
+        clock.advance(secs(5));
+        agg.update(&2);
+        agg.tick();
+        assert_eq!(counts(&sink), vec![(0, 1, 1.0)]);
+
+        // A long quiet spell costs nothing and produces nothing.
+        clock.advance(Duration::from_secs(1_000_000_000));
+        agg.tick();
+        assert_eq!(counts(&sink), vec![(0, 1, 1.0), (5, 1, 2.0)]);
+    }
+
+    #[test]
+    fn test_sliding_windows_overlap() {
+        let clock = ManualClock::new();
+        let sink = Collector::default();
+        let agg = WindowAggregator::with_clock(
+            &sink,
+            Window::Sliding {
+                size: secs(10),
+                step: secs(5),
+            },
+            |n: &u32| f64::from(*n),
+            &clock,
+        );
+
+        // One event at 1 s, 6 s, 11 s and 16 s.
+        clock.advance(secs(1));
+        for n in 1..=4 {
+            agg.update(&n);
+            clock.advance(secs(5));
+        }
+        // Now at 21 s.
+        agg.tick();
+
+        assert_eq!(counts(&sink), vec![(0, 2, 3.0), (5, 2, 5.0), (10, 2, 7.0)]);
+    }
+}
@@ -0,0 +1,130 @@
+//! Bridges and clients living in separate processes.
+//!
+//! The child processes are this test binary re-run with `--exact child`, which
+//! makes [`child`] act on the role passed through the environment.
+
+#![cfg(unix)]
+
+use std::cell::RefCell;
+use std::env;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Child;
+use std::process::Command;
+use std::process::Stdio;
+use std::thread;
+use std::time::Duration;
+
+use demo::event::IEventObserver;
+use demo::ipc::SocketBridge;
+use demo::ipc::SocketClient;
+
+const ROLE: &str = "DEMO_IPC_ROLE";
+const SOCKET: &str = "DEMO_IPC_SOCKET";
+
+fn socket_path(name: &str) -> PathBuf {
+    env::temp_dir()
+        .join(format!("demo-ipc-it-{}-{name}.sock", std::process::id()))
+}
+
+fn spawn_child(role: &str, path: &Path) -> Child {
+    Command::new(env::current_exe().unwrap())
+        .args(["--exact", "child", "--nocapture", "--test-threads=1"])
+        .env(ROLE, role)
+        .env(SOCKET, path)
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap()
+}
+
+#[derive(Default)]
+struct Recorder {
+    seen: RefCell<Vec<String>>,
+}
+
+impl IEventObserver<String> for Recorder {
+    fn update(&self, event: &String) {
+        self.seen.borrow_mut().push(event.clone());
+    }
+}
+
+/// Entry point for child processes; does nothing in a normal test run.
+#[test]
+fn child() {
+    let Ok(role) = env::var(ROLE) else { return };
+    let path = PathBuf::from(env::var(SOCKET).unwrap());
+    match role.as_str() {
+        // Prints every event it receives.
+        "client" => {
+            let recorder = Recorder::default();
+            SocketClient::new(path).run(&recorder).unwrap();
+            for event in recorder.seen.into_inner() {
+                println!("event: {event}");
+            }
+        },
+        // Publishes one event, then hangs until killed.
+        "crashing-bridge" => {
+            let bridge = SocketBridge::bind(path).unwrap();
+            assert!(bridge.wait_for_clients(1, Duration::from_secs(10)));
+            bridge.update(&"from first process".to_string());
+            loop {
+                thread::park();
+            }
+        },
+        // Publishes one event and shuts down cleanly.
+        "bridge" => {
+            let bridge = SocketBridge::bind(path).unwrap();
+            assert!(bridge.wait_for_clients(1, Duration::from_secs(10)));
+            bridge.update(&"from second process".to_string());
+        },
+        other => panic!("unknown role {other}"),
+    }
+}
+
+#[test]
+fn test_events_reach_child_process() {
+    let path = socket_path("to-child");
+    let bridge = SocketBridge::bind(&path).unwrap();
+    let child = spawn_child("client", &path);
+    assert!(bridge.wait_for_clients(1, Duration::from_secs(10)));
+
+    for event in ["one", "two", "three"] {
+        bridge.update(&event.to_string());
+    }
+    drop(bridge);
+
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    let events: Vec<_> = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        // The harness prints "test child ... " in front of the first line.
+        .filter_map(|line| line.split_once("event: "))
+        .map(|(_, event)| event.to_owned())
+        .collect();
+    assert_eq!(events, vec!["one", "two", "three"]);
+}
+
+#[test]
+fn test_client_survives_bridge_restart() {
+    let path = socket_path("restart");
+    let mut first = spawn_child("crashing-bridge", &path);
+    let mut client = SocketClient::<String>::new(&path)
+        .retry(200, Duration::from_millis(25));
+
+    assert_eq!(
+        client.recv().unwrap().as_deref(),
+        Some("from first process")
+    );
+    first.kill().unwrap();
+    first.wait().unwrap();
+    let second = spawn_child("bridge", &path);
+
+    assert_eq!(
+        client.recv().unwrap().as_deref(),
+        Some("from second process")
+    );
+    assert_eq!(client.recv().unwrap(), None);
+    assert_eq!(client.reconnects(), 1);
+    assert!(second.wait_with_output().unwrap().status.success());
+}